@@ -20,4 +20,16 @@ fn calculate_static_deadlocks(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, calculate_unused_floors, calculate_static_deadlocks);
+fn calculate_dead_squares(c: &mut Criterion) {
+    let map = Map::from_str(WORLDCUP2014).unwrap();
+    c.bench_function("deadlock::calculate_dead_squares", |b| {
+        b.iter(|| black_box(deadlock::calculate_dead_squares(black_box(&map))))
+    });
+}
+
+criterion_group!(
+    benches,
+    calculate_unused_floors,
+    calculate_static_deadlocks,
+    calculate_dead_squares
+);
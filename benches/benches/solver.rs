@@ -3,7 +3,7 @@ use std::str::FromStr;
 
 use criterion::{criterion_group, Criterion};
 use sokoban_core::{
-    solver::{Solver, Strategy},
+    solver::{Heuristic, Solver, SolverConfig, Strategy},
     Level,
 };
 
@@ -49,6 +49,75 @@ fn a_star_search(c: &mut Criterion) {
 // bench_solve(level);
 // }
 
+fn ablation(c: &mut Criterion) {
+    let level = load_level_from_file("assets/BoxWorld_100.xsb", 3);
+    let configs = [
+        ("default", SolverConfig::new()),
+        (
+            "no tunnel macros",
+            SolverConfig::new().with_tunnel_macros(false),
+        ),
+        (
+            "no freeze deadlocks",
+            SolverConfig::new().with_freeze_deadlocks(false),
+        ),
+        (
+            "no closed diagonal deadlocks",
+            SolverConfig::new().with_closed_diagonal_deadlocks(false),
+        ),
+        (
+            "no frozen corral deadlocks",
+            SolverConfig::new().with_frozen_corral_deadlocks(false),
+        ),
+        (
+            "no bipartite deadlocks",
+            SolverConfig::new().with_bipartite_deadlocks(false),
+        ),
+        (
+            "no static deadlocks",
+            SolverConfig::new().with_static_deadlocks(false),
+        ),
+        (
+            "no normalization",
+            SolverConfig::new().with_normalization(false),
+        ),
+        (
+            "no corral pruning",
+            SolverConfig::new().with_corral_pruning(false),
+        ),
+        (
+            "no deadlock patterns",
+            SolverConfig::new().with_deadlock_patterns(false),
+        ),
+        (
+            "no deadlock learning",
+            SolverConfig::new().with_deadlock_learning(0),
+        ),
+        (
+            "packing order",
+            SolverConfig::new().with_packing_order(true),
+        ),
+        ("goal macros", SolverConfig::new().with_goal_macros(true)),
+        (
+            "minimum matching",
+            SolverConfig::new().with_heuristic(Heuristic::MinimumMatching),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("Solver::a_star_search ablation");
+    for (name, config) in configs {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                // Create a new solver each time, so no deadlocks are learned
+                // from previous searches
+                let solver = Solver::with_config(level.map().clone(), Strategy::Fast, config);
+                black_box(solver.a_star_search().unwrap());
+            })
+        });
+    }
+    group.finish();
+}
+
 fn tunnels(c: &mut Criterion) {
     let level = Level::from_str(PATH).unwrap();
     let solver = Solver::new(level.map().clone(), Strategy::Fast);
@@ -61,4 +130,4 @@ fn tunnels(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, a_star_search, tunnels);
+criterion_group!(benches, a_star_search, ablation, tunnels);
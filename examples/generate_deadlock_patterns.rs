@@ -0,0 +1,14 @@
+//! Generates the deadlock pattern database shipped with the crate.
+//!
+//! Run with `cargo run --release --example generate_deadlock_patterns`.
+
+use std::fs;
+
+use sokoban_core::deadlock_patterns::DeadlockPatterns;
+
+fn main() {
+    let patterns = DeadlockPatterns::generate();
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/src/deadlock_patterns.bin");
+    fs::write(path, patterns.to_bytes()).expect("failed to write the database");
+    println!("{} patterns written to {path}", patterns.len());
+}
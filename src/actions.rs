@@ -9,6 +9,7 @@ use std::{
 use crate::{action::Action, error::ParseActionsError, math::IVector2, run_length::rle_decode};
 
 /// Secondary statistics for a sequence of actions.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default)]
 pub struct SecondaryValues {
     /// Straight line box pushes.
     pub box_lines: i32,
@@ -20,6 +21,64 @@ pub struct SecondaryValues {
     pub player_lines: i32,
 }
 
+/// Incrementally computes the secondary values of a sequence of actions.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default)]
+pub(crate) struct SecondaryValuesCounter {
+    values: SecondaryValues,
+    player_position: IVector2,
+    prev_pushed_box_position: Option<IVector2>,
+    prev_action: Option<Action>,
+}
+
+impl SecondaryValuesCounter {
+    /// Counts the next action.
+    pub fn count(&mut self, action: Action) {
+        self.player_position += &action.direction().into();
+        if let Some(prev_action) = self.prev_action {
+            if action.direction() != prev_action.direction() {
+                self.values.player_lines += 1;
+            }
+            if action.is_push() {
+                if action != prev_action {
+                    self.values.box_lines += 1;
+                    if !prev_action.is_push() {
+                        self.values.pushing_sessions += 1;
+                    }
+                }
+                if let Some(prev_pushed_box_position) = self.prev_pushed_box_position {
+                    if self.player_position != prev_pushed_box_position {
+                        self.values.box_changes += 1;
+                    }
+                }
+                self.prev_pushed_box_position =
+                    Some(self.player_position + &action.direction().into());
+            }
+        } else {
+            self.values.player_lines += 1;
+        }
+        self.prev_action = Some(action);
+    }
+
+    /// Returns the last counted action.
+    pub fn prev_action(&self) -> Option<Action> {
+        self.prev_action
+    }
+
+    /// Returns `true` if a pushed box is being tracked for box changes.
+    pub fn has_prev_pushed_box(&self) -> bool {
+        self.prev_pushed_box_position.is_some()
+    }
+
+    /// Returns the secondary values of the counted actions.
+    pub fn values(&self) -> SecondaryValues {
+        let mut values = self.values;
+        if self.prev_pushed_box_position.is_some() {
+            values.box_changes += 1;
+        }
+        values
+    }
+}
+
 /// A owned, mutable actions (akin to [`Vec<Action>`]).
 #[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
 pub struct Actions(pub Vec<Action>);
@@ -42,48 +101,11 @@ impl Actions {
 
     /// Returns the secondary values.
     pub fn secondary_values(&self) -> SecondaryValues {
-        let mut box_lines = 0;
-        let mut box_changes = 0;
-        let mut pushing_sessions = 0;
-        let mut player_lines = 0;
-
-        let mut player_position = IVector2::zeros();
-        let mut prev_pushed_box_position = None;
-        let mut prev_action: Option<Action> = None;
+        let mut counter = SecondaryValuesCounter::default();
         for action in &self.0 {
-            player_position += &action.direction().into();
-            if let Some(prev_action) = prev_action {
-                if action.direction() != prev_action.direction() {
-                    player_lines += 1;
-                }
-                if action.is_push() {
-                    if *action != prev_action {
-                        box_lines += 1;
-                        if !prev_action.is_push() {
-                            pushing_sessions += 1;
-                        }
-                    }
-                    if let Some(prev_pushed_box_position) = prev_pushed_box_position {
-                        if player_position != prev_pushed_box_position {
-                            box_changes += 1;
-                        }
-                    }
-                    prev_pushed_box_position = Some(player_position + &action.direction().into());
-                }
-            } else {
-                player_lines += 1;
-            }
-            prev_action = Some(*action);
-        }
-        if prev_pushed_box_position.is_some() {
-            box_changes += 1;
-        }
-        SecondaryValues {
-            box_lines,
-            box_changes,
-            pushing_sessions,
-            player_lines,
+            counter.count(*action);
         }
+        counter.values()
     }
 }
 
@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+
+use crate::{
+    deadlock::is_freeze_deadlock_by,
+    direction::Direction,
+    solver::Solver,
+    state::{BitSet, State},
+    Tiles,
+};
+
+/// The maximum number of states searched to prove a corral deadlock.
+const DEADLOCK_SEARCH_LIMIT: usize = 256;
+
+/// A player-inaccessible corral (PI-corral).
+///
+/// A corral is an area the player can not reach, enclosed by walls and boxes.
+/// It is player-inaccessible if every box next to it can only be pushed into
+/// it, and the player can reach every side such pushes are made from. Unless
+/// the boxes of the corral are already solved, some push of them must happen
+/// in any solution, and it can be moved before every other push without
+/// changing the number of pushes. So the other pushes can be pruned.
+#[derive(Clone, Debug)]
+pub struct Corral {
+    /// The floor cells of the corral not occupied by boxes.
+    cells: BitSet,
+    /// The boxes next to the cells of the corral.
+    boxes: BitSet,
+}
+
+impl Corral {
+    /// Finds the PI-corral of the state whose boxes can be pushed in the fewest
+    /// ways, or returns `None` if there is no PI-corral to be solved.
+    pub fn find(state: &State, player_distances: &[i32], solver: &Solver) -> Option<Self> {
+        let floors = solver.floors();
+        let mut visited = BitSet::new(floors.len());
+        let mut best: Option<(usize, Self)> = None;
+        for start in 0..floors.len() {
+            if player_distances[start] != i32::MAX
+                || state.boxes().contains(start)
+                || visited.contains(start)
+            {
+                continue;
+            }
+
+            // Flood fill the corral, collecting the boxes around it
+            let mut corral = Self {
+                cells: BitSet::new(floors.len()),
+                boxes: BitSet::new(floors.len()),
+            };
+            visited.insert(start);
+            corral.cells.insert(start);
+            let mut stack = vec![start];
+            while let Some(index) = stack.pop() {
+                for direction in Direction::iter() {
+                    let Some(neighbor) = floors.neighbor(index, direction) else {
+                        continue;
+                    };
+                    if state.boxes().contains(neighbor) {
+                        corral.boxes.insert(neighbor);
+                    } else if !visited.contains(neighbor) {
+                        visited.insert(neighbor);
+                        corral.cells.insert(neighbor);
+                        stack.push(neighbor);
+                    }
+                }
+            }
+
+            if !corral.is_unsolved(solver) {
+                continue;
+            }
+            let Some(pushes) = corral.pushes(player_distances, solver) else {
+                continue;
+            };
+            if best
+                .as_ref()
+                .is_none_or(|(min_pushes, _)| pushes < *min_pushes)
+            {
+                best = Some((pushes, corral));
+            }
+        }
+        best.map(|(_, corral)| corral)
+    }
+
+    /// Returns `true` if there is a box of the corral at the floor index.
+    pub fn contains_box(&self, index: usize) -> bool {
+        self.boxes.contains(index)
+    }
+
+    /// Returns `true` if the boxes of the corral can not be pushed to goals,
+    /// even with all other boxes removed.
+    ///
+    /// The search gives up once the player enters the corral, or after
+    /// searching a limited number of states. Proven deadlocks are learned by
+    /// the solver.
+    pub fn is_deadlock(&self, state: &State, solver: &Solver) -> bool {
+        let floors = solver.floors();
+        let initial_state = State::new(
+            state.player_position,
+            self.boxes.iter().map(|index| floors.position(index)),
+            floors,
+        );
+        let player_region = {
+            let player_distances = initial_state.player_distances(floors);
+            let mut player_region = BitSet::new(floors.len());
+            for index in (0..floors.len()).filter(|&index| player_distances[index] != i32::MAX) {
+                player_region.insert(index);
+            }
+            player_region
+        };
+        let mut visited = HashSet::from([initial_state.normalized_hash(floors)]);
+        let mut stack = vec![initial_state];
+        while let Some(state) = stack.pop() {
+            if visited.len() > DEADLOCK_SEARCH_LIMIT
+                || state.boxes().difference(floors.goals()).next().is_none()
+            {
+                return false;
+            }
+            let player_distances = state.player_distances(floors);
+            if self
+                .cells
+                .iter()
+                .any(|index| player_distances[index] != i32::MAX)
+            {
+                return false;
+            }
+
+            for box_index in state.boxes().iter() {
+                for push_direction in Direction::iter() {
+                    let Some(player_index) = floors.neighbor(box_index, -push_direction) else {
+                        continue;
+                    };
+                    if player_distances[player_index] == i32::MAX {
+                        continue;
+                    }
+                    let Some(new_box_index) = floors.neighbor(box_index, push_direction) else {
+                        continue;
+                    };
+                    let new_box_position = floors.position(new_box_index);
+                    if state.boxes().contains(new_box_index)
+                        || !solver.lower_bounds().contains_key(&new_box_position)
+                    {
+                        continue;
+                    }
+
+                    let mut new_state = state.clone();
+                    new_state.move_box(box_index, new_box_index, floors);
+                    new_state.player_position = floors.position(box_index);
+                    if !solver.map()[new_box_position].intersects(Tiles::Goal)
+                        && is_freeze_deadlock_by(
+                            solver.map(),
+                            new_box_position,
+                            &|position| new_state.has_box(position, floors),
+                            &mut HashSet::new(),
+                        )
+                    {
+                        continue;
+                    }
+                    if visited.insert(new_state.normalized_hash(floors)) {
+                        stack.push(new_state);
+                    }
+                }
+            }
+        }
+        solver.learn_deadlock(self.boxes.clone(), player_region);
+        true
+    }
+
+    /// Returns `true` if some box of the corral must be pushed to solve the
+    /// state, which is when a box is not on a goal or a goal is empty.
+    fn is_unsolved(&self, solver: &Solver) -> bool {
+        let goals = solver.floors().goals();
+        self.boxes.difference(goals).next().is_some()
+            || self.cells.iter().any(|index| goals.contains(index))
+    }
+
+    /// Returns the number of pushes of the boxes of the corral, or `None` if
+    /// the corral is not player-inaccessible.
+    fn pushes(&self, player_distances: &[i32], solver: &Solver) -> Option<usize> {
+        let floors = solver.floors();
+        let mut pushes = 0;
+        for box_index in self.boxes.iter() {
+            for push_direction in Direction::iter() {
+                // The player can not stand inside the corral before its boxes
+                // are pushed
+                let Some(player_index) = floors.neighbor(box_index, -push_direction) else {
+                    continue;
+                };
+                if self.cells.contains(player_index) || self.boxes.contains(player_index) {
+                    continue;
+                }
+                let Some(new_box_index) = floors.neighbor(box_index, push_direction) else {
+                    continue;
+                };
+                if self.boxes.contains(new_box_index) {
+                    continue;
+                }
+                // Boxes outside the corral may be pushed away, so the push out
+                // of the corral may become possible later
+                if !self.cells.contains(new_box_index) || player_distances[player_index] == i32::MAX
+                {
+                    return None;
+                }
+                pushes += 1;
+            }
+        }
+        Some(pushes)
+    }
+}
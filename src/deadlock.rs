@@ -2,7 +2,14 @@
 
 use std::collections::{HashSet, VecDeque};
 
-use crate::{direction::Direction, map::Map, math::IVector2, tiles::Tiles};
+use crate::{
+    direction::Direction,
+    map::Map,
+    math::IVector2,
+    push_distances::PushDistances,
+    state::{BitSet, Floors},
+    tiles::Tiles,
+};
 
 /// Checks if the given box position is a static deadlock.
 ///
@@ -55,7 +62,23 @@ pub fn is_freeze_deadlock(
     box_positions: &HashSet<IVector2>,
     visited: &mut HashSet<IVector2>,
 ) -> bool {
-    debug_assert!(box_positions.contains(&box_position));
+    is_freeze_deadlock_by(
+        map,
+        box_position,
+        &|position| box_positions.contains(&position),
+        visited,
+    )
+}
+
+/// Checks if the given box position is a freeze deadlock, where the box
+/// positions are queried through `has_box`.
+pub(crate) fn is_freeze_deadlock_by(
+    map: &Map,
+    box_position: IVector2,
+    has_box: &impl Fn(IVector2) -> bool,
+    visited: &mut HashSet<IVector2>,
+) -> bool {
+    debug_assert!(has_box(box_position));
 
     if !visited.insert(box_position) {
         return true;
@@ -80,10 +103,8 @@ pub fn is_freeze_deadlock(
         }
 
         // Check if any immovable boxes on the axis.
-        if (box_positions.contains(&neighbors[0])
-            && is_freeze_deadlock(map, neighbors[0], box_positions, visited))
-            || (box_positions.contains(&neighbors[1])
-                && is_freeze_deadlock(map, neighbors[1], box_positions, visited))
+        if (has_box(neighbors[0]) && is_freeze_deadlock_by(map, neighbors[0], has_box, visited))
+            || (has_box(neighbors[1]) && is_freeze_deadlock_by(map, neighbors[1], has_box, visited))
         {
             continue;
         }
@@ -93,11 +114,404 @@ pub fn is_freeze_deadlock(
     true
 }
 
+/// Checks if an empty cell near the given box position is enclosed by boxes
+/// which can never be moved, and the cell or any of these boxes is not a
+/// goal.
+///
+/// The player can not enter an enclosed cell, so the boxes around it can not
+/// be pushed away from it, and can only be pushed into it from the far side,
+/// which is useless if the box would be frozen there. This detects diagonal
+/// formations of boxes missed by [`is_freeze_deadlock`], which only looks
+/// along the axes of each box.
+pub fn is_closed_diagonal_deadlock(
+    map: &Map,
+    box_position: IVector2,
+    box_positions: &HashSet<IVector2>,
+    player_position: IVector2,
+) -> bool {
+    is_closed_diagonal_deadlock_by(
+        map,
+        box_position,
+        &|position| box_positions.contains(&position),
+        player_position,
+    )
+}
+
+/// Checks if an empty cell near the given box position is enclosed by boxes
+/// which can never be moved, where the box positions are queried through
+/// `has_box`.
+pub(crate) fn is_closed_diagonal_deadlock_by(
+    map: &Map,
+    box_position: IVector2,
+    has_box: &impl Fn(IVector2) -> bool,
+    player_position: IVector2,
+) -> bool {
+    // The box either encloses the cell, or blocks a box enclosing it
+    for y in -2..=2i32 {
+        for x in -2 + y.abs()..=2 - y.abs() {
+            let cell = box_position + IVector2::new(x, y);
+            let Some(boxes) = enclosing_frozen_boxes(map, cell, has_box, player_position) else {
+                continue;
+            };
+            if map[cell].intersects(Tiles::Goal)
+                || boxes
+                    .iter()
+                    .any(|&position| !map[position].intersects(Tiles::Goal))
+            {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Checks if an area enclosed by walls and frozen boxes contains fewer goals
+/// than boxes.
+///
+/// Boxes can not leave such an area, so the boxes in it can not all be pushed
+/// to goals. Frozen boxes which are not on goals are left to
+/// [`is_freeze_deadlock`] and [`is_closed_diagonal_deadlock`].
+pub fn is_frozen_corral_deadlock(
+    map: &Map,
+    box_positions: &HashSet<IVector2>,
+    player_position: IVector2,
+) -> bool {
+    is_frozen_corral_deadlock_by(
+        map,
+        box_positions.iter().copied(),
+        &|position| box_positions.contains(&position),
+        player_position,
+    )
+}
+
+/// Checks if an area enclosed by walls and frozen boxes contains fewer goals
+/// than boxes, where the box positions are queried through `has_box`.
+pub(crate) fn is_frozen_corral_deadlock_by(
+    map: &Map,
+    box_positions: impl IntoIterator<Item = IVector2>,
+    has_box: &impl Fn(IVector2) -> bool,
+    player_position: IVector2,
+) -> bool {
+    let box_positions: Vec<IVector2> = box_positions.into_iter().collect();
+    let frozen_boxes = frozen_boxes(map, &box_positions, has_box, player_position);
+    overfilled_area(map, &box_positions, has_box, frozen_boxes).is_some()
+}
+
+/// Returns the boxes of an area enclosed by walls and the frozen boxes which
+/// contains fewer goals than boxes.
+fn overfilled_area(
+    map: &Map,
+    box_positions: &[IVector2],
+    has_box: &impl Fn(IVector2) -> bool,
+    frozen_boxes: HashSet<IVector2>,
+) -> Option<Vec<IVector2>> {
+    let mut visited = frozen_boxes;
+    for &start in box_positions {
+        if !visited.insert(start) {
+            continue;
+        }
+        // Collect the boxes and count the goals of the area around the box
+        let mut boxes = Vec::new();
+        let mut goals = 0;
+        let mut stack = vec![start];
+        while let Some(position) = stack.pop() {
+            if has_box(position) {
+                boxes.push(position);
+            }
+            goals += map[position].intersects(Tiles::Goal) as usize;
+            for direction in Direction::iter() {
+                let neighbor = position + &direction.into();
+                if !map[neighbor].intersects(Tiles::Wall) && visited.insert(neighbor) {
+                    stack.push(neighbor);
+                }
+            }
+        }
+        if goals < boxes.len() {
+            return Some(boxes);
+        }
+    }
+    None
+}
+
+/// Returns the boxes which can never be moved, found by the freeze and
+/// closed diagonal deadlock detections.
+fn frozen_boxes(
+    map: &Map,
+    box_positions: &[IVector2],
+    has_box: &impl Fn(IVector2) -> bool,
+    player_position: IVector2,
+) -> HashSet<IVector2> {
+    let mut frozen_boxes = HashSet::new();
+    for &box_position in box_positions {
+        if is_freeze_deadlock_by(map, box_position, has_box, &mut HashSet::new()) {
+            frozen_boxes.insert(box_position);
+        }
+        for direction in Direction::iter() {
+            let cell = box_position + &direction.into();
+            if let Some(boxes) = enclosing_frozen_boxes(map, cell, has_box, player_position) {
+                frozen_boxes.extend(boxes);
+            }
+        }
+    }
+    frozen_boxes
+}
+
+/// Returns the boxes around the empty cell if the cell is enclosed by walls
+/// and boxes which can never be moved.
+fn enclosing_frozen_boxes(
+    map: &Map,
+    cell: IVector2,
+    has_box: &impl Fn(IVector2) -> bool,
+    player_position: IVector2,
+) -> Option<Vec<IVector2>> {
+    if cell == player_position
+        || !map.in_bounds(cell)
+        || map[cell].intersects(Tiles::Wall)
+        || !map[cell].intersects(Tiles::Floor | Tiles::Box | Tiles::Goal)
+        || has_box(cell)
+    {
+        return None;
+    }
+    let mut boxes = Vec::new();
+    for direction in Direction::iter() {
+        let neighbor = cell + &direction.into();
+        if map[neighbor].intersects(Tiles::Wall) {
+            continue;
+        }
+        if !has_box(neighbor) {
+            return None;
+        }
+        boxes.push((neighbor, direction));
+    }
+    if boxes.is_empty() {
+        return None;
+    }
+
+    // Each box is assumed to be frozen while checking the others
+    let is_blocked = |position: IVector2| {
+        map[position].intersects(Tiles::Wall)
+            || (has_box(position)
+                && is_freeze_deadlock_by(
+                    map,
+                    position,
+                    has_box,
+                    &mut boxes.iter().map(|&(position, _)| position).collect(),
+                ))
+    };
+    for &(box_position, direction) in &boxes {
+        // The box can only be pushed into the cell from the far side, where it
+        // would be frozen unless the cell is a goal
+        if !is_blocked(box_position + &direction.into())
+            && (map[cell].intersects(Tiles::Goal)
+                || !is_freeze_deadlock_by(
+                    map,
+                    cell,
+                    &|position| position == cell || (position != box_position && has_box(position)),
+                    &mut HashSet::new(),
+                ))
+        {
+            return None;
+        }
+        let [side, other_side] = match direction {
+            Direction::Up | Direction::Down => [Direction::Left, Direction::Right],
+            Direction::Left | Direction::Right => [Direction::Up, Direction::Down],
+        };
+        if !is_blocked(box_position + &side.into())
+            && !is_blocked(box_position + &other_side.into())
+        {
+            return None;
+        }
+    }
+    Some(boxes.into_iter().map(|(position, _)| position).collect())
+}
+
+/// Checks if the boxes can not be pushed to distinct goals.
+///
+/// Each box can only be pushed to the goals reachable by pushing it alone. If
+/// there is no matching assigning every box a distinct reachable goal, such
+/// as two boxes which can only reach the same goal, the state is a deadlock.
+pub fn is_bipartite_deadlock(map: &Map, box_positions: &HashSet<IVector2>) -> bool {
+    is_bipartite_deadlock_by(&PushDistances::new(map), box_positions.iter().copied())
+}
+
+/// Checks if the boxes can not be pushed to distinct goals, where the
+/// reachable goals are queried through `push_distances`.
+pub(crate) fn is_bipartite_deadlock_by(
+    push_distances: &PushDistances,
+    box_positions: impl IntoIterator<Item = IVector2>,
+) -> bool {
+    unmatched_boxes(push_distances, box_positions).is_some()
+}
+
+/// Returns the indices of a set of boxes which can reach fewer goals than
+/// there are boxes in the set, or `None` if every box can be matched to a
+/// distinct reachable goal.
+fn unmatched_boxes(
+    push_distances: &PushDistances,
+    box_positions: impl IntoIterator<Item = IVector2>,
+) -> Option<Vec<usize>> {
+    let reachable_goals: Vec<Vec<usize>> = box_positions
+        .into_iter()
+        .map(|box_position| push_distances.reachable_goals(box_position).collect())
+        .collect();
+    let mut box_of_goal = vec![None; push_distances.goal_count()];
+    for box_ in 0..reachable_goals.len() {
+        let mut visited = vec![false; box_of_goal.len()];
+        if !augment(box_, &reachable_goals, &mut box_of_goal, &mut visited) {
+            // The goals visited are all matched to the boxes reaching them
+            let mut boxes: Vec<usize> = (0..visited.len())
+                .filter(|&goal| visited[goal])
+                .filter_map(|goal| box_of_goal[goal])
+                .collect();
+            boxes.push(box_);
+            return Some(boxes);
+        }
+    }
+    None
+}
+
+/// Matches the box to a goal along an augmenting path.
+fn augment(
+    box_: usize,
+    reachable_goals: &[Vec<usize>],
+    box_of_goal: &mut [Option<usize>],
+    visited: &mut [bool],
+) -> bool {
+    for &goal in &reachable_goals[box_] {
+        if visited[goal] {
+            continue;
+        }
+        visited[goal] = true;
+        if box_of_goal[goal]
+            .is_none_or(|other_box| augment(other_box, reachable_goals, box_of_goal, visited))
+        {
+            box_of_goal[goal] = Some(box_);
+            return true;
+        }
+    }
+    false
+}
+
+/// The kind of a deadlock.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum DeadlockKind {
+    /// Boxes on dead squares, from which they can not be pushed to any goal.
+    Static,
+    /// Boxes not on goals which can never be moved.
+    Freeze,
+    /// Boxes in an area enclosed by frozen boxes with fewer goals than boxes.
+    Corral,
+    /// Boxes which can only be pushed to fewer goals than there are boxes.
+    Bipartite,
+}
+
+/// A deadlock, with the boxes causing it.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Deadlock {
+    /// The kind of the deadlock.
+    pub kind: DeadlockKind,
+    /// The positions of the boxes causing the deadlock.
+    pub box_positions: HashSet<IVector2>,
+}
+
+/// The tables used by [`find_deadlock`], which only depend on the terrain and
+/// goals of the map.
+///
+/// They are expensive to calculate, so they are kept while the boxes move.
+#[derive(Clone, Debug)]
+pub(crate) struct DeadlockTables {
+    floors: Floors,
+    push_distances: PushDistances,
+    /// The dead squares, indexed by `floors`.
+    dead_squares: BitSet,
+}
+
+impl DeadlockTables {
+    /// Calculates the tables of the map.
+    pub fn new(map: &Map) -> Self {
+        let floors = Floors::new(map);
+        let push_distances = PushDistances::new(map);
+        let mut dead_squares = BitSet::new(floors.len());
+        for index in 0..floors.len() {
+            if push_distances.get_nearest(floors.position(index)).is_none() {
+                dead_squares.insert(index);
+            }
+        }
+        Self {
+            floors,
+            push_distances,
+            dead_squares,
+        }
+    }
+
+    /// Returns `true` if a lone box at the position can not be pushed to any
+    /// goal.
+    fn is_dead_square(&self, position: IVector2) -> bool {
+        self.floors
+            .index(position)
+            .is_none_or(|index| self.dead_squares.contains(index))
+    }
+}
+
+/// Finds a deadlock of the map using all deadlock detections.
+///
+/// Returns `None` if no deadlock is detected, which does not mean the map is
+/// solvable.
+pub fn find_deadlock(map: &Map) -> Option<Deadlock> {
+    find_deadlock_by(map, &DeadlockTables::new(map))
+}
+
+/// Finds a deadlock of the map using all deadlock detections, with the tables
+/// of its terrain.
+pub(crate) fn find_deadlock_by(map: &Map, tables: &DeadlockTables) -> Option<Deadlock> {
+    let box_positions: Vec<IVector2> = map.box_positions().iter().copied().collect();
+    let has_box = |position| map.box_positions().contains(&position);
+    let deadlock = |kind, box_positions: Vec<IVector2>| {
+        Some(Deadlock {
+            kind,
+            box_positions: box_positions.into_iter().collect(),
+        })
+    };
+
+    let dead_boxes: Vec<IVector2> = box_positions
+        .iter()
+        .copied()
+        .filter(|&position| tables.is_dead_square(position))
+        .collect();
+    if !dead_boxes.is_empty() {
+        return deadlock(DeadlockKind::Static, dead_boxes);
+    }
+
+    let frozen_boxes = frozen_boxes(map, &box_positions, &has_box, map.player_position());
+    let frozen_boxes_not_on_goals: Vec<IVector2> = frozen_boxes
+        .iter()
+        .copied()
+        .filter(|&position| !map[position].intersects(Tiles::Goal))
+        .collect();
+    if !frozen_boxes_not_on_goals.is_empty() {
+        return deadlock(DeadlockKind::Freeze, frozen_boxes_not_on_goals);
+    }
+
+    if let Some(boxes) = overfilled_area(map, &box_positions, &has_box, frozen_boxes) {
+        return deadlock(DeadlockKind::Corral, boxes);
+    }
+
+    if let Some(boxes) = unmatched_boxes(&tables.push_distances, box_positions.iter().copied()) {
+        return deadlock(
+            DeadlockKind::Bipartite,
+            boxes.into_iter().map(|i| box_positions[i]).collect(),
+        );
+    }
+    None
+}
+
 /// Calculates static deadlock positions independent of the player's position.
 ///
 /// This function returns an **incomplete** set of dead positions independent
 /// of the player's position. Any box pushed to a point in the set will cause a
 /// deadlock, regardless of the player's position.
+///
+/// Consider using [`calculate_dead_squares`] for the complete set.
 pub fn calculate_static_deadlocks(map: &Map) -> HashSet<IVector2> {
     let mut dead_positions = HashSet::new();
     for x in 1..map.dimensions().x - 1 {
@@ -149,6 +563,29 @@ pub fn calculate_static_deadlocks(map: &Map) -> HashSet<IVector2> {
     dead_positions
 }
 
+/// Calculates the dead squares of the map.
+///
+/// A dead square is a floor from which a lone box can not be pushed to any
+/// goal, wherever the player is. They are found by pulling a box from each
+/// goal to every reachable position, so unlike
+/// [`calculate_static_deadlocks`], the returned set is complete.
+pub fn calculate_dead_squares(map: &Map) -> HashSet<IVector2> {
+    let push_distances = PushDistances::new(map);
+    let mut dead_squares = HashSet::new();
+    for y in 0..map.dimensions().y {
+        for x in 0..map.dimensions().x {
+            let position = IVector2::new(x, y);
+            if map[position].intersects(Tiles::Floor | Tiles::Box | Tiles::Goal)
+                && !map[position].intersects(Tiles::Wall)
+                && push_distances.get_nearest(position).is_none()
+            {
+                dead_squares.insert(position);
+            }
+        }
+    }
+    dead_squares
+}
+
 /// Calculate the positions of the useless floors.
 pub fn calculate_useless_floors(mut map: Map) -> HashSet<IVector2> {
     let mut useless_floors = HashSet::new();
@@ -195,12 +632,15 @@ pub fn calculate_useless_floors(mut map: Map) -> HashSet<IVector2> {
 }
 
 /// Calculate the positions of the useless boxes.
+///
+/// These are the boxes which can never be moved, either frozen along their
+/// axes or enclosing a cell in a closed diagonal.
 pub fn calculate_useless_boxes(map: &Map) -> HashSet<IVector2> {
-    map.box_positions()
-        .iter()
-        .cloned()
-        .filter(|&position| {
-            is_freeze_deadlock(map, position, map.box_positions(), &mut HashSet::new())
-        })
-        .collect()
+    let box_positions: Vec<IVector2> = map.box_positions().iter().copied().collect();
+    frozen_boxes(
+        map,
+        &box_positions,
+        &|position| map.box_positions().contains(&position),
+        map.player_position(),
+    )
 }
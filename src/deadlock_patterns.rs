@@ -0,0 +1,369 @@
+//! A database of deadlock patterns.
+//!
+//! Patterns use 3x3 windows rather than 4x4 ones. Every window containing the
+//! pushed box is checked, so a 2x2 square of boxes and walls is matched
+//! together with a ring of context cells on each side. The 3^9 windows of
+//! this size can be searched exhaustively and stored as a direct lookup
+//! table, while the 3^16 (about 43 million) 4x4 windows are too many to
+//! search and store, and would need a slower subpattern lookup for each push.
+//! Deadlocks spanning larger regions, such as areas whose boxes can not all
+//! reach distinct goals, are left to
+//! [`is_bipartite_deadlock`](crate::deadlock::is_bipartite_deadlock).
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::OnceLock,
+};
+
+use crate::{
+    direction::Direction, error::DecodeDeadlockPatternsError, map::Map, math::IVector2,
+    tiles::Tiles,
+};
+
+/// The width and height of the window of a pattern.
+const SIZE: i32 = 3;
+
+/// The number of cells of the window of a pattern.
+const CELLS: usize = (SIZE * SIZE) as usize;
+
+/// The header of the binary form of the database.
+const MAGIC: &[u8; 4] = b"SKDP";
+
+/// The version of the binary form of the database.
+const VERSION: u8 = 2;
+
+/// The content of a cell of a pattern.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Cell {
+    Empty = 0,
+    Wall = 1,
+    Box = 2,
+}
+
+/// A database of small box and wall configurations which are provably
+/// unsolvable.
+///
+/// Each pattern is a 3x3 window of walls, boxes and empty cells, with the
+/// fewest boxes which remain in the window whatever boxes are pushed, with the
+/// player starting outside the window. The cells around the window are assumed
+/// to be empty floors. Since walls and boxes outside the window can only block
+/// more pushes, a state containing the pattern is a deadlock if the window has
+/// fewer goals than these boxes.
+///
+/// Besides frozen boxes, this finds boxes which can move but not leave the
+/// window, such as a box which can only be pushed into a pocket the player can
+/// not enter, which the freeze check misses.
+///
+/// Patterns are stored in canonical form, and all 8 symmetries are added when
+/// the database is loaded.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct DeadlockPatterns {
+    /// The fewest remaining boxes of each pattern, keyed by the encoded window.
+    patterns: HashMap<u32, u8>,
+}
+
+impl DeadlockPatterns {
+    /// Returns the database shipped with the crate.
+    pub fn builtin() -> &'static Self {
+        static PATTERNS: OnceLock<DeadlockPatterns> = OnceLock::new();
+        PATTERNS.get_or_init(|| {
+            Self::from_bytes(include_bytes!("deadlock_patterns.bin"))
+                .expect("invalid builtin deadlock patterns")
+        })
+    }
+
+    /// Generates the database by searching every pattern.
+    ///
+    /// This is slow, so the database is generated offline and stored with
+    /// [`to_bytes`](Self::to_bytes).
+    pub fn generate() -> Self {
+        let mut instance = Self::default();
+        for mut key in 0..3u32.pow(CELLS as u32) {
+            let mut cells = [Cell::Empty; CELLS];
+            for cell in &mut cells {
+                *cell = match key % 3 {
+                    0 => Cell::Empty,
+                    1 => Cell::Wall,
+                    _ => Cell::Box,
+                };
+                key /= 3;
+            }
+            let remaining_boxes = remaining_boxes(&cells);
+            if remaining_boxes != 0 {
+                instance.patterns.insert(encode(&cells), remaining_boxes);
+            }
+        }
+        instance
+    }
+
+    /// Decodes the database from its binary form.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeDeadlockPatternsError> {
+        let Some((header, mut data)) = bytes.split_first_chunk::<8>() else {
+            return Err(DecodeDeadlockPatternsError::InvalidHeader);
+        };
+        if &header[..4] != MAGIC || header[4] != VERSION || header[5..7] != [SIZE as u8; 2] {
+            return Err(DecodeDeadlockPatternsError::InvalidHeader);
+        }
+        let mut instance = Self::default();
+        while !data.is_empty() {
+            let Some((entry, rest)) = data.split_first_chunk::<4>() else {
+                return Err(DecodeDeadlockPatternsError::Truncated);
+            };
+            let entry = u32::from_le_bytes(*entry);
+            let key = entry & ((1 << (CELLS * 2)) - 1);
+            let remaining_boxes = (entry >> (CELLS * 2)) as u8;
+            instance.patterns.extend(
+                symmetries(key)
+                    .into_iter()
+                    .map(|key| (key, remaining_boxes)),
+            );
+            data = rest;
+        }
+        Ok(instance)
+    }
+
+    /// Encodes the database into its binary form.
+    ///
+    /// Only the canonical form of each pattern is stored.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut entries: Vec<u32> = self
+            .patterns
+            .iter()
+            .filter(|(&key, _)| symmetries(key).iter().all(|&other_key| key <= other_key))
+            .map(|(&key, &remaining_boxes)| key | (remaining_boxes as u32) << (CELLS * 2))
+            .collect();
+        entries.sort_unstable();
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([VERSION, SIZE as u8, SIZE as u8, 0]);
+        for entry in entries {
+            bytes.extend(entry.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Returns the number of patterns, including symmetric ones.
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    /// Returns `true` if there are no patterns.
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Checks if any window containing the box position matches a pattern
+    /// with fewer goals in the window than its remaining boxes.
+    ///
+    /// Positions which are not floors of the map are treated as walls. Windows
+    /// where the player is in an area which can not be reached from outside the
+    /// window are skipped.
+    pub fn is_deadlock(
+        &self,
+        map: &Map,
+        box_position: IVector2,
+        has_box: impl Fn(IVector2) -> bool,
+        player_position: IVector2,
+    ) -> bool {
+        for offset_y in 0..SIZE {
+            for offset_x in 0..SIZE {
+                let origin = box_position - IVector2::new(offset_x, offset_y);
+                let mut cells = [Cell::Empty; CELLS];
+                for (i, cell) in cells.iter_mut().enumerate() {
+                    let position = origin + cell_offset(i);
+                    *cell = if !map.in_bounds(position)
+                        || map[position].intersects(Tiles::Wall)
+                        || !map[position].intersects(Tiles::Floor | Tiles::Box | Tiles::Goal)
+                    {
+                        Cell::Wall
+                    } else if has_box(position) {
+                        Cell::Box
+                    } else {
+                        Cell::Empty
+                    };
+                }
+                let Some(&remaining_boxes) = self.patterns.get(&encode(&cells)) else {
+                    continue;
+                };
+                if let Some(i) = cell_index(player_position - origin) {
+                    let walls = mask(&cells, Cell::Wall);
+                    let boxes = mask(&cells, Cell::Box);
+                    if player_region(walls, boxes, None).0 & (1 << i) == 0 {
+                        continue;
+                    }
+                }
+                let goals = (0..CELLS)
+                    .map(|i| origin + cell_offset(i))
+                    .filter(|&position| {
+                        map.in_bounds(position) && map[position].intersects(Tiles::Goal)
+                    })
+                    .count();
+                if goals < remaining_boxes as usize {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Returns the offset of the cell in the window.
+fn cell_offset(i: usize) -> IVector2 {
+    IVector2::new(i as i32 % SIZE, i as i32 / SIZE)
+}
+
+fn encode(cells: &[Cell; CELLS]) -> u32 {
+    cells
+        .iter()
+        .enumerate()
+        .map(|(i, &cell)| (cell as u32) << (i * 2))
+        .sum()
+}
+
+fn decode(key: u32) -> [Cell; CELLS] {
+    std::array::from_fn(|i| match (key >> (i * 2)) & 0b11 {
+        0 => Cell::Empty,
+        1 => Cell::Wall,
+        _ => Cell::Box,
+    })
+}
+
+/// Returns the 8 symmetric forms of the pattern.
+///
+/// The pattern is converted to a map, which is rotated and flipped.
+fn symmetries(key: u32) -> [u32; 8] {
+    let cells = decode(key);
+    let mut map = Map::with_dimensions(IVector2::new(SIZE, SIZE));
+    for (i, &cell) in cells.iter().enumerate() {
+        map[cell_offset(i)] = match cell {
+            Cell::Empty => Tiles::Floor,
+            Cell::Wall => Tiles::Wall,
+            Cell::Box => Tiles::Box,
+        };
+    }
+
+    std::array::from_fn(|i| {
+        map.rotate();
+        if i == 4 {
+            map.flip();
+        }
+        let cells = std::array::from_fn(|i| {
+            let tiles = map[cell_offset(i)];
+            if tiles.intersects(Tiles::Wall) {
+                Cell::Wall
+            } else if tiles.intersects(Tiles::Box) {
+                Cell::Box
+            } else {
+                Cell::Empty
+            }
+        });
+        encode(&cells)
+    })
+}
+
+/// Returns the fewest boxes remaining in the window of the pattern.
+///
+/// All states reachable by pushing boxes are searched, where boxes pushed out
+/// of the window are removed, and the player starts outside the window.
+fn remaining_boxes(cells: &[Cell; CELLS]) -> u8 {
+    let walls = mask(cells, Cell::Wall);
+    let boxes = mask(cells, Cell::Box);
+
+    let initial_state = (boxes, player_region(walls, boxes, None));
+    let mut visited = HashSet::from([initial_state]);
+    let mut queue = VecDeque::from([initial_state]);
+    let mut remaining_boxes = boxes.count_ones();
+    while let Some((boxes, region)) = queue.pop_front() {
+        remaining_boxes = remaining_boxes.min(boxes.count_ones());
+        if remaining_boxes == 0 {
+            break;
+        }
+        for i in (0..CELLS).filter(|&i| boxes & (1 << i) != 0) {
+            let position = cell_offset(i);
+            for direction in Direction::iter() {
+                let player_position = position - &direction.into();
+                let can_reach = match cell_index(player_position) {
+                    Some(j) => region.0 & (1 << j) != 0,
+                    None => region.1,
+                };
+                if !can_reach {
+                    continue;
+                }
+                let mut new_boxes = boxes & !(1 << i);
+                match cell_index(position + &direction.into()) {
+                    Some(j) if (walls | boxes) & (1 << j) != 0 => continue,
+                    Some(j) => new_boxes |= 1 << j,
+                    None => {}
+                }
+                let state = (new_boxes, player_region(walls, new_boxes, Some(i)));
+                if visited.insert(state) {
+                    queue.push_back(state);
+                }
+            }
+        }
+    }
+    remaining_boxes as u8
+}
+
+/// Returns the cells reachable by the player from the cell, or from outside
+/// the window if `None`, and whether the outside is reachable.
+fn player_region(walls: u16, boxes: u16, start: Option<usize>) -> (u16, bool) {
+    let mut region = 0u16;
+    let mut outside = start.is_none();
+    let mut stack = Vec::new();
+    match start {
+        Some(i) => {
+            region |= 1 << i;
+            stack.push(i);
+        }
+        None => {
+            // Enter the window from every border cell
+            for i in 0..CELLS {
+                let position = cell_offset(i);
+                if Direction::iter()
+                    .any(|direction| cell_index(position + &direction.into()).is_none())
+                    && (walls | boxes) & (1 << i) == 0
+                {
+                    region |= 1 << i;
+                    stack.push(i);
+                }
+            }
+        }
+    }
+    while let Some(i) = stack.pop() {
+        for direction in Direction::iter() {
+            match cell_index(cell_offset(i) + &direction.into()) {
+                Some(j) if (walls | boxes | region) & (1 << j) == 0 => {
+                    region |= 1 << j;
+                    stack.push(j);
+                }
+                Some(_) => {}
+                None if !outside => {
+                    // Leaving the window reaches all border cells
+                    let (outside_region, _) = player_region(walls, boxes, None);
+                    region |= outside_region;
+                    outside = true;
+                    stack.extend((0..CELLS).filter(|&j| outside_region & (1 << j) != 0));
+                }
+                None => {}
+            }
+        }
+    }
+    (region, outside)
+}
+
+/// Returns the index of the cell at the offset, or `None` if it is outside
+/// the window.
+fn cell_index(offset: IVector2) -> Option<usize> {
+    ((0..SIZE).contains(&offset.x) && (0..SIZE).contains(&offset.y))
+        .then(|| (offset.y * SIZE + offset.x) as usize)
+}
+
+fn mask(cells: &[Cell; CELLS], kind: Cell) -> u16 {
+    cells
+        .iter()
+        .enumerate()
+        .filter(|(_, &cell)| cell == kind)
+        .map(|(i, _)| 1 << i)
+        .sum()
+}
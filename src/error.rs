@@ -1,5 +1,7 @@
 //! Error types.
 
+use std::fmt;
+
 use thiserror::Error;
 
 /// An error which can be returned when parsing a level.
@@ -81,15 +83,50 @@ pub enum DecodeRleError {
     EndWithDigits(usize),
 }
 
+/// An error which can be returned when decoding a deadlock pattern database.
+#[derive(Error, Clone, Eq, PartialEq, Debug)]
+pub enum DecodeDeadlockPatternsError {
+    /// The header is missing or does not match the format.
+    #[error("invalid header")]
+    InvalidHeader,
+    /// The data ends in the middle of a pattern.
+    #[error("truncated data")]
+    Truncated,
+}
+
 /// An error which can be returned when searching for a solution.
 #[derive(Error, Clone, Eq, PartialEq, Debug)]
 pub enum SearchError {
     /// No solution found.
     #[error("no solution found")]
     NoSolution,
-    /// Search was terminated early (timeout or iteration limit reached).
-    #[error("search terminated")]
-    Terminated,
+    /// Search was terminated early by the terminator of the solver.
+    #[error("search terminated: {0}")]
+    Terminated(TerminationReason),
+}
+
+/// The condition which terminated a search.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum TerminationReason {
+    /// The timeout elapsed.
+    Timeout,
+    /// The iteration limit was reached.
+    Iterations,
+    /// The node limit was reached.
+    Nodes,
+    /// The search was cancelled.
+    Cancelled,
+}
+
+impl fmt::Display for TerminationReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "timeout"),
+            Self::Iterations => write!(f, "iteration limit reached"),
+            Self::Nodes => write!(f, "node limit reached"),
+            Self::Cancelled => write!(f, "cancelled"),
+        }
+    }
 }
 
 /// An error which can be returned when level do/undo/redo actions.
@@ -109,6 +146,29 @@ pub enum ActionError {
     NoUndoneActions,
 }
 
+/// An error which can be returned when verifying a solution.
+#[derive(Error, Clone, Eq, PartialEq, Debug)]
+pub enum VerifyError {
+    /// The action at the index is blocked.
+    #[error("action {index} is illegal: {error}")]
+    IllegalAction {
+        /// The index of the action.
+        index: usize,
+        /// The reason the action is illegal.
+        error: ActionError,
+    },
+    /// The action at the index is a move labeled as a push, or a push labeled
+    /// as a move.
+    #[error("action {index} is mislabeled")]
+    MislabeledAction {
+        /// The index of the action.
+        index: usize,
+    },
+    /// The actions do not solve the map.
+    #[error("map is not solved")]
+    Unsolved,
+}
+
 /// An error which can be returned when managing a collection.
 #[derive(Error, Clone, Eq, PartialEq, Debug)]
 pub enum CollectionError {
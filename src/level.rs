@@ -13,22 +13,29 @@ use itertools::Itertools;
 use crate::{
     action::Action,
     actions::Actions,
+    deadlock::{find_deadlock, find_deadlock_by, Deadlock, DeadlockTables},
     direction::Direction,
-    error::{ActionError, ParseLevelError, ParseMapError},
+    error::{ActionError, ParseLevelError, ParseMapError, SearchError},
     map::Map,
     math::IVector2,
     path_finding::reachable_area,
+    solver::{Solver, Strategy, Terminator},
     tiles::Tiles,
 };
 
 /// A level.
-#[derive(Clone, Eq, PartialEq, Debug)]
+#[derive(Clone, Debug)]
 pub struct Level {
     map: Map,
     metadata: BTreeMap<String, String>,
     actions: Actions,
     undone_actions: Actions,
     map_hash: u64,
+    detects_deadlocks: bool,
+    deadlock: Option<Deadlock>,
+    /// The tables of the terrain used to detect deadlocks, calculated once
+    /// deadlock detection is enabled.
+    deadlock_tables: Option<DeadlockTables>,
 }
 
 impl Level {
@@ -41,6 +48,9 @@ impl Level {
             actions: Actions::default(),
             undone_actions: Actions::default(),
             map_hash,
+            detects_deadlocks: false,
+            deadlock: None,
+            deadlock_tables: None,
         }
     }
 
@@ -56,6 +66,8 @@ impl Level {
 
     /// Returns a mutable reference to the map of the level.
     pub fn map_mut(&mut self) -> &mut Map {
+        // The terrain may change
+        self.deadlock_tables = None;
         &mut self.map
     }
 
@@ -74,6 +86,31 @@ impl Level {
         self.map_hash
     }
 
+    /// Enables or disables deadlock detection after each push.
+    ///
+    /// The tables of the terrain used by the detection are calculated once
+    /// when it is enabled, so each push only checks the boxes.
+    pub fn set_deadlock_detection(&mut self, enabled: bool) {
+        self.detects_deadlocks = enabled;
+        if !enabled {
+            self.deadlock_tables = None;
+        }
+        self.update_deadlock();
+    }
+
+    /// Returns `true` if deadlocks are detected after each push.
+    pub fn detects_deadlocks(&self) -> bool {
+        self.detects_deadlocks
+    }
+
+    /// Returns the deadlock of the current position, if deadlock detection is
+    /// enabled and a deadlock is detected.
+    ///
+    /// The deadlock is updated by pushes, and by undoing and redoing them.
+    pub fn deadlock(&self) -> Option<&Deadlock> {
+        self.deadlock.as_ref()
+    }
+
     /// Performs a sequence of actions on the level.
     pub fn do_actions<I: IntoIterator<Item = Direction>>(
         &mut self,
@@ -99,10 +136,12 @@ impl Level {
             self.map
                 .set_box_position(new_player_position, new_box_position);
             self.actions.push(Action::Push(direction));
+            self.map.set_player_position(new_player_position);
+            self.update_deadlock();
         } else {
             self.actions.push(Action::Move(direction));
+            self.map.set_player_position(new_player_position);
         }
-        self.map.set_player_position(new_player_position);
         self.undone_actions.clear();
         Ok(())
     }
@@ -117,6 +156,9 @@ impl Level {
             }
             let prev_player_position = self.map.player_position() - &last_action.direction().into();
             self.map.set_player_position(prev_player_position);
+            if last_action.is_push() {
+                self.update_deadlock();
+            }
             self.undone_actions.push(last_action);
             Ok(())
         } else {
@@ -141,6 +183,51 @@ impl Level {
         self.map.box_positions() == self.map.goal_positions()
     }
 
+    /// Returns a hint of the next push to solve the level from the current
+    /// position.
+    ///
+    /// The solver is run with the strategy until the terminator fires. Returns
+    /// `Ok(None)` if the level is solved, and [`SearchError::NoSolution`] if the
+    /// position is deadlocked.
+    pub fn hint(
+        &self,
+        strategy: Strategy,
+        terminator: Terminator,
+    ) -> Result<Option<Hint>, SearchError> {
+        if self.is_solved() {
+            return Ok(None);
+        }
+        let deadlock = match &self.deadlock_tables {
+            Some(tables) => find_deadlock_by(&self.map, tables),
+            None => find_deadlock(&self.map),
+        };
+        if deadlock.is_some() {
+            return Err(SearchError::NoSolution);
+        }
+        let solution = Solver::new(self.map.clone(), strategy)
+            .with_terminator(terminator)
+            .a_star_search()?;
+
+        // The solution of an unsolved level starts with a walk to a push
+        let hint = solution
+            .iter()
+            .find_position(|action| action.is_push())
+            .map(|(index, push)| {
+                let walk = Actions(solution[..index].to_vec());
+                let player_position = walk
+                    .iter()
+                    .fold(self.map.player_position(), |position, action| {
+                        position + &action.direction().into()
+                    });
+                Hint {
+                    box_position: player_position + &push.direction().into(),
+                    direction: push.direction(),
+                    walk,
+                }
+            });
+        Ok(hint)
+    }
+
     /// Returns the reachable area for the player.
     pub fn player_reachable_area(&self) -> HashSet<IVector2> {
         reachable_area(self.map.player_position(), |position| {
@@ -227,6 +314,43 @@ impl Level {
             }
         })
     }
+
+    /// Detects the deadlock of the current position if enabled.
+    fn update_deadlock(&mut self) {
+        if !self.detects_deadlocks {
+            self.deadlock = None;
+            return;
+        }
+        let tables = self
+            .deadlock_tables
+            .get_or_insert_with(|| DeadlockTables::new(&self.map));
+        self.deadlock = find_deadlock_by(&self.map, tables);
+    }
+}
+
+impl PartialEq for Level {
+    /// Compares the maps, metadata and actions of the levels, ignoring whether
+    /// deadlocks are detected.
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+            && self.metadata == other.metadata
+            && self.actions == other.actions
+            && self.undone_actions == other.undone_actions
+            && self.map_hash == other.map_hash
+    }
+}
+
+impl Eq for Level {}
+
+/// A hint of the next push to solve a level.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Hint {
+    /// The position of the box to push.
+    pub box_position: IVector2,
+    /// The direction to push the box.
+    pub direction: Direction,
+    /// The moves of the player to the box before the push.
+    pub walk: Actions,
 }
 
 impl fmt::Display for Level {
@@ -352,6 +476,9 @@ impl FromStr for Level {
             actions: Actions::default(),
             undone_actions: Actions::default(),
             map_hash,
+            detects_deadlocks: false,
+            deadlock: None,
+            deadlock_tables: None,
         })
     }
 }
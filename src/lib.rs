@@ -6,16 +6,22 @@ pub mod action;
 pub mod actions;
 pub mod collection;
 pub mod deadlock;
+pub mod deadlock_patterns;
 pub mod direction;
 pub mod error;
 pub mod level;
 pub mod map;
 pub mod math;
+pub mod packing_order;
 pub mod path_finding;
+pub mod push_distances;
 pub mod run_length;
 pub mod solver;
 pub mod tiles;
+pub mod tunnels;
 
+mod corral;
+mod matching;
 mod node;
 mod state;
 
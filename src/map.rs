@@ -9,8 +9,15 @@ use std::{
 };
 
 use crate::{
-    actions::Actions, deadlock::*, direction::Direction, error::ParseMapError, level::Level,
-    math::IVector2, path_finding::*, run_length::rle_decode, state::State, tiles::Tiles,
+    actions::{Actions, SecondaryValues},
+    deadlock::*,
+    direction::Direction,
+    error::{ParseMapError, VerifyError},
+    level::Level,
+    math::IVector2,
+    path_finding::*,
+    run_length::rle_decode,
+    tiles::Tiles,
 };
 
 /// A grid-based map.
@@ -148,6 +155,38 @@ impl Map {
         self.box_positions == self.goal_positions
     }
 
+    /// Verifies that the actions solve the map.
+    ///
+    /// The actions are replayed from the initial state of the map, and each
+    /// action must be labeled as a push exactly if it pushes a box.
+    pub fn verify_solution(&self, actions: &Actions) -> Result<SolutionReport, VerifyError> {
+        if !self.replay(actions)?.is_solved() {
+            return Err(VerifyError::Unsolved);
+        }
+        Ok(SolutionReport {
+            moves: actions.moves(),
+            pushes: actions.pushes(),
+            secondary_values: actions.secondary_values(),
+        })
+    }
+
+    /// Replays the actions from the initial state of the map, and returns the
+    /// level after them.
+    ///
+    /// Each action must be labeled as a push exactly if it pushes a box.
+    pub(crate) fn replay(&self, actions: &Actions) -> Result<Level, VerifyError> {
+        let mut level = Level::from_map(self.clone());
+        for (index, action) in actions.iter().enumerate() {
+            level
+                .do_action(action.direction())
+                .map_err(|error| VerifyError::IllegalAction { index, error })?;
+            if level.actions().last() != Some(action) {
+                return Err(VerifyError::MislabeledAction { index });
+            }
+        }
+        Ok(level)
+    }
+
     /// Normalizes the map.
     ///
     /// Remove elements from the map that are not relevant to the solution.
@@ -626,13 +665,15 @@ impl fmt::Display for Map {
     }
 }
 
-impl From<Map> for State {
-    fn from(map: Map) -> Self {
-        Self {
-            player_position: map.player_position,
-            box_positions: map.box_positions,
-        }
-    }
+/// The metrics of a verified solution.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub struct SolutionReport {
+    /// The number of moves, including pushes.
+    pub moves: usize,
+    /// The number of pushes.
+    pub pushes: usize,
+    /// The secondary values.
+    pub secondary_values: SecondaryValues,
 }
 
 fn calculate_dimensions_and_player_position(actions: &Actions) -> (IVector2, IVector2) {
@@ -0,0 +1,149 @@
+use crate::{solver::Solver, state::State};
+
+/// The cost of pushing a box to a goal it can never reach.
+///
+/// It is larger than the cost of any matching without such pairs.
+const INFINITY: i64 = 1 << 32;
+
+/// Marks a box or goal which is not matched yet.
+const UNMATCHED: usize = usize::MAX;
+
+/// A minimum cost perfect matching between boxes and goals.
+///
+/// The cost of a pair is the number of pushes needed to push the box to the
+/// goal, so the cost of the matching is a lower bound of the pushes needed to
+/// solve the state. The matching is computed with the Hungarian algorithm and
+/// keeps its dual potentials, so it can be updated in `O(n^2)` after a push.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Matching {
+    /// The floor index of each box.
+    boxes: Vec<usize>,
+    goal_of_box: Vec<usize>,
+    box_of_goal: Vec<usize>,
+    box_potentials: Vec<i64>,
+    goal_potentials: Vec<i64>,
+    cost: i64,
+}
+
+impl Matching {
+    /// Creates the minimum cost matching of the boxes in the state.
+    pub fn new(state: &State, solver: &Solver) -> Self {
+        let boxes: Vec<usize> = state.boxes().iter().collect();
+        let count = boxes.len();
+        let mut instance = Self {
+            boxes,
+            goal_of_box: vec![UNMATCHED; count],
+            box_of_goal: vec![UNMATCHED; count],
+            box_potentials: vec![0; count],
+            goal_potentials: vec![0; count],
+            cost: 0,
+        };
+        for box_ in 0..count {
+            instance.augment(box_, solver);
+        }
+        instance.update_cost(solver);
+        instance
+    }
+
+    /// Updates the matching after a box is pushed between two floor cells.
+    ///
+    /// Only the pushed box is unmatched and matched again, which keeps the
+    /// matching optimal since the potentials of the other boxes remain valid.
+    pub fn push(&mut self, from: usize, to: usize, solver: &Solver) {
+        let box_ = self
+            .boxes
+            .iter()
+            .position(|&index| index == from)
+            .expect("no box at the position");
+        self.boxes[box_] = to;
+
+        let goal = self.goal_of_box[box_];
+        self.box_of_goal[goal] = UNMATCHED;
+        self.goal_of_box[box_] = UNMATCHED;
+        self.box_potentials[box_] = (0..self.boxes.len())
+            .map(|goal| self.pair_cost(box_, goal, solver) - self.goal_potentials[goal])
+            .min()
+            .unwrap();
+        self.augment(box_, solver);
+        self.update_cost(solver);
+    }
+
+    /// Returns the cost of the matching, or `None` if some box can not be
+    /// matched to a goal it can reach.
+    pub fn cost(&self) -> Option<i32> {
+        (self.cost < INFINITY).then_some(self.cost as i32)
+    }
+
+    fn pair_cost(&self, box_: usize, goal: usize, solver: &Solver) -> i64 {
+        match solver.push_distances().by_index(goal, self.boxes[box_]) {
+            i32::MAX => INFINITY,
+            distance => distance as i64,
+        }
+    }
+
+    /// Matches the unmatched box along the shortest augmenting path.
+    fn augment(&mut self, box_: usize, solver: &Solver) {
+        let count = self.boxes.len();
+        // The index `count` is a virtual goal matched to the new box
+        let mut min_slacks = vec![i64::MAX; count];
+        let mut visited = vec![false; count];
+        let mut previous_goals = vec![count; count];
+        let mut current_box = box_;
+        let mut current_goal = count;
+        loop {
+            if current_goal < count {
+                visited[current_goal] = true;
+            }
+            let mut delta = i64::MAX;
+            let mut next_goal = count;
+            for goal in (0..count).filter(|&goal| !visited[goal]) {
+                let slack = self.pair_cost(current_box, goal, solver)
+                    - self.box_potentials[current_box]
+                    - self.goal_potentials[goal];
+                if slack < min_slacks[goal] {
+                    min_slacks[goal] = slack;
+                    previous_goals[goal] = current_goal;
+                }
+                if min_slacks[goal] < delta {
+                    delta = min_slacks[goal];
+                    next_goal = goal;
+                }
+            }
+
+            self.box_potentials[box_] += delta;
+            for goal in 0..count {
+                if visited[goal] {
+                    self.box_potentials[self.box_of_goal[goal]] += delta;
+                    self.goal_potentials[goal] -= delta;
+                } else {
+                    min_slacks[goal] -= delta;
+                }
+            }
+
+            current_goal = next_goal;
+            match self.box_of_goal[current_goal] {
+                UNMATCHED => break,
+                matched_box => current_box = matched_box,
+            }
+        }
+
+        // Flip the matched pairs along the augmenting path
+        while current_goal != count {
+            let previous_goal = previous_goals[current_goal];
+            let matched_box = if previous_goal == count {
+                box_
+            } else {
+                self.box_of_goal[previous_goal]
+            };
+            self.box_of_goal[current_goal] = matched_box;
+            self.goal_of_box[matched_box] = current_goal;
+            current_goal = previous_goal;
+        }
+    }
+
+    fn update_cost(&mut self, solver: &Solver) {
+        self.cost = (0..self.boxes.len())
+            .map(|box_| self.pair_cost(box_, self.goal_of_box[box_], solver))
+            .sum();
+    }
+}
@@ -1,118 +1,410 @@
 use std::{cmp::Ordering, collections::HashSet};
 
 use crate::{
-    deadlock::is_freeze_deadlock,
+    actions::SecondaryValuesCounter,
+    corral::Corral,
+    deadlock::{
+        is_bipartite_deadlock_by, is_closed_diagonal_deadlock_by, is_freeze_deadlock_by,
+        is_frozen_corral_deadlock_by,
+    },
+    deadlock_patterns::DeadlockPatterns,
     direction::Direction,
-    path_finding::{find_path, reachable_area},
-    solver::{Solver, Strategy},
-    state::State,
-    Tiles,
+    matching::Matching,
+    solver::{Heuristic, Solver, Strategy},
+    state::{PlayerWalks, State},
+    Action, SecondaryValues, Tiles,
 };
 
+/// The priority of a node, compared lexicographically.
+pub type Priority = [i32; 6];
+
 /// A node in the search tree.
 #[derive(Clone, Eq, Debug)]
 pub struct Node {
     pub state: State,
     pub pushes: i32,
     pub moves: i32,
-    priority: i32,
+    /// The secondary values of the actions leading to this node, only counted
+    /// if the strategy minimizes them.
+    pub secondary_values: SecondaryValuesCounter,
+    /// The hash used to detect duplicate states.
+    pub hash: u64,
+    /// The matching between boxes and goals, only kept if the heuristic is
+    /// [`Heuristic::MinimumMatching`].
+    matching: Option<Matching>,
+    /// The lower bound of the pushes needed to solve the state, or `None` if
+    /// the state is proven unsolvable.
+    heuristic: Option<i32>,
+    priority: Priority,
 }
 
 impl Node {
     /// Creates a new `Node`.
-    pub fn new(state: State, pushes: i32, moves: i32, solver: &Solver) -> Self {
-        let heuristic = state.heuristic(solver);
-        let priority = match solver.strategy() {
-            Strategy::Fast => heuristic,
-            Strategy::OptimalPush => pushes + heuristic,
-            Strategy::OptimalMove => moves + heuristic,
+    pub fn new(
+        state: State,
+        pushes: i32,
+        moves: i32,
+        secondary_values: SecondaryValuesCounter,
+        solver: &Solver,
+    ) -> Self {
+        let matching = match solver.config().heuristic() {
+            Heuristic::NearestGoal => None,
+            Heuristic::MinimumMatching => Some(Matching::new(&state, solver)),
         };
-        Self {
+        Self::with_matching(state, pushes, moves, secondary_values, matching, solver)
+    }
+
+    /// Creates a new `Node` with the matching of its boxes.
+    fn with_matching(
+        state: State,
+        pushes: i32,
+        moves: i32,
+        secondary_values: SecondaryValuesCounter,
+        matching: Option<Matching>,
+        solver: &Solver,
+    ) -> Self {
+        let mut node = Self {
+            hash: solver.state_hash(&state, &secondary_values),
             state,
             pushes,
             moves,
-            priority,
+            secondary_values,
+            matching,
+            heuristic: None,
+            priority: Priority::default(),
+        };
+        node.heuristic = match &node.matching {
+            Some(matching) => matching.cost(),
+            None => node.state.heuristic(solver),
+        };
+        // Draw the search to fill the goals in the packing order
+        if let Some(packing_order) = (solver.config().follows_packing_order()
+            && solver.strategy() == Strategy::Fast)
+            .then(|| solver.packing_order())
+            .flatten()
+        {
+            node.heuristic = node
+                .heuristic
+                .and_then(|_| packing_order.heuristic(node.state.boxes(), solver.push_distances()));
+        }
+        let push_heuristic = node.heuristic();
+        let move_heuristic = push_heuristic + node.state.walk_lower_bound(solver);
+        let mut priority = node.cost(solver.strategy());
+        match solver.strategy() {
+            Strategy::Fast => priority = [push_heuristic, 0, 0, 0, 0, 0],
+            Strategy::OptimalPush => priority[0] += push_heuristic,
+            Strategy::OptimalMove | Strategy::OptimalMovePush => {
+                priority[0] += move_heuristic;
+                priority[1] += push_heuristic;
+            }
+            Strategy::OptimalPushMove => {
+                priority[0] += push_heuristic;
+                priority[1] += move_heuristic;
+            }
+        }
+        node.priority = priority;
+        node
+    }
+
+    /// Returns the cost of the path from the initial node to this node, in
+    /// the metrics minimized by the strategy.
+    pub fn cost(&self, strategy: Strategy) -> Priority {
+        let SecondaryValues {
+            box_lines,
+            box_changes,
+            pushing_sessions,
+            player_lines,
+        } = self.secondary_values.values();
+        match strategy {
+            Strategy::Fast | Strategy::OptimalPush => [self.pushes, 0, 0, 0, 0, 0],
+            Strategy::OptimalMove => [self.moves, self.pushes, 0, 0, 0, 0],
+            Strategy::OptimalMovePush => [
+                self.moves,
+                self.pushes,
+                box_lines,
+                box_changes,
+                pushing_sessions,
+                player_lines,
+            ],
+            Strategy::OptimalPushMove => [
+                self.pushes,
+                self.moves,
+                box_lines,
+                box_changes,
+                pushing_sessions,
+                player_lines,
+            ],
         }
     }
 
+    /// Returns `true` if the heuristic proves that the state is unsolvable.
+    pub fn is_deadlock(&self) -> bool {
+        self.heuristic.is_none()
+    }
+
+    /// Returns the lower bound of the pushes needed to solve the state.
+    pub fn heuristic(&self) -> i32 {
+        self.heuristic.unwrap_or(0)
+    }
+
+    /// Returns the estimated total cost of the solution through this node.
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
     /// Returns the successors of the node.
     pub fn successors(&self, solver: &Solver) -> Vec<Node> {
         let mut successors = Vec::new();
-        let player_reachable_area = reachable_area(self.state.player_position, |position| {
-            !solver.map()[position].intersects(Tiles::Wall)
-                && !self.state.box_positions.contains(&position)
+        let floors = solver.floors();
+        let config = solver.config();
+        let player_distances = self.state.player_distances(floors);
+        let corral = config
+            .corral_pruning()
+            .then(|| Corral::find(&self.state, &player_distances, solver))
+            .flatten();
+        if let Some(corral) = &corral {
+            if corral.is_deadlock(&self.state, solver) {
+                solver.record_stats(|stats| stats.deadlocks_pruned += 1);
+                return successors;
+            }
+        }
+        let restricts_pushes = matches!(solver.strategy(), Strategy::Fast | Strategy::OptimalPush);
+        let packing_order = (config.follows_packing_order() && solver.strategy() == Strategy::Fast)
+            .then(|| solver.packing_order())
+            .flatten();
+        let goal_room = (config.goal_macros() && solver.strategy() == Strategy::Fast)
+            .then(|| solver.packing_order())
+            .flatten();
+        let player_walks = solver.strategy().minimizes_secondary_values().then(|| {
+            let facing = self.secondary_values.prev_action().map(|a| a.direction());
+            PlayerWalks::new(&self.state, floors, facing)
         });
         // Creates successor states by pushing boxes
-        for box_position in &self.state.box_positions {
+        for box_index in self.state.boxes().iter() {
+            // Only pushes of the boxes of the PI-corral are needed
+            if restricts_pushes && corral.as_ref().is_some_and(|c| !c.contains_box(box_index)) {
+                continue;
+            }
             for push_direction in Direction::iter() {
-                let mut new_box_position = box_position + &push_direction.into();
+                // Checks if the player can push the box
+                let Some(player_index) = floors.neighbor(box_index, -push_direction) else {
+                    continue;
+                };
+                if player_distances[player_index] == i32::MAX {
+                    continue;
+                }
 
                 // Checks if the box can be pushed
-                if solver.map()[new_box_position].intersects(Tiles::Wall)
-                    || self.state.box_positions.contains(&new_box_position)
-                    || !solver.lower_bounds().contains_key(&new_box_position)
-                {
+                let Some(mut new_box_index) = floors.neighbor(box_index, push_direction) else {
+                    continue;
+                };
+                if self.state.boxes().contains(new_box_index) {
                     continue;
                 }
-
-                // Checks if the player can push the box
-                if !player_reachable_area.contains(&(box_position - &push_direction.into())) {
+                // Skip dead squares
+                if config.static_deadlocks()
+                    && !solver
+                        .lower_bounds()
+                        .contains_key(&floors.position(new_box_index))
+                {
+                    solver.record_stats(|stats| stats.deadlocks_pruned += 1);
                     continue;
                 }
 
-                let mut new_player_position = *box_position;
-
+                let mut new_player_index = box_index;
                 let mut new_pushes = self.pushes + 1;
-                let mut new_moves = self.moves
-                    + find_path(
-                        self.state.player_position,
-                        new_player_position,
-                        |position| {
-                            !solver.map()[position].intersects(Tiles::Wall)
-                                && (!self.state.box_positions.contains(&position)
-                                    || position == *box_position)
-                        },
-                    )
-                    .unwrap()
-                    .len() as i32
-                    - 1;
-
-                // Skip no influence pushes
-                while solver
-                    .tunnels()
-                    .contains(&(new_box_position, push_direction))
+                let mut new_moves = self.moves + player_distances[player_index] + 1;
+
+                if let Some(tunnel) = config
+                    .tunnel_macros()
+                    .then(|| solver.tunnels().get(floors.position(new_box_index)))
+                    .flatten()
                 {
-                    new_player_position = new_box_position;
-                    new_box_position += &push_direction.into();
-                    new_pushes += 1;
-                    new_moves += 1;
+                    // Skip pushes into tunnels against their push directions,
+                    // including every push into a player tunnel
+                    if !tunnel.cells().contains(&floors.position(box_index))
+                        && !tunnel.push_directions().contains(&push_direction)
+                    {
+                        solver.record_stats(|stats| stats.deadlocks_pruned += 1);
+                        continue;
+                    }
+
+                    // Push the box on to the last cell of the tunnel, since the
+                    // player can not pass it there or get around it to push
+                    // it back
+                    while !tunnel.has_detour()
+                        && !solver.map()[floors.position(new_box_index)].intersects(Tiles::Goal)
+                    {
+                        let Some(next_box_index) = floors
+                            .neighbor(new_box_index, push_direction)
+                            .filter(|&index| tunnel.cells().contains(&floors.position(index)))
+                        else {
+                            break;
+                        };
+                        if self.state.boxes().contains(next_box_index)
+                            || !solver
+                                .lower_bounds()
+                                .contains_key(&floors.position(next_box_index))
+                        {
+                            break;
+                        }
+                        new_player_index = new_box_index;
+                        new_box_index = next_box_index;
+                        new_pushes += 1;
+                        new_moves += 1;
+                    }
                 }
 
-                let mut new_box_positions = self.state.box_positions.clone();
-                new_box_positions.remove(box_position);
-                new_box_positions.insert(new_box_position);
+                // Push boxes entering the goal room on to the next goal
+                if let Some(goal_room) = goal_room {
+                    if goal_room.is_entrance(new_box_index) && !goal_room.contains(box_index) {
+                        let mut state = self.state.clone();
+                        state.move_box(box_index, new_box_index, floors);
+                        state.player_position = floors.position(new_player_index);
+                        let push_directions = goal_room
+                            .next_goal(state.boxes())
+                            .and_then(|goal| state.box_pushes(new_box_index, goal, floors));
+                        for direction in push_directions.into_iter().flatten() {
+                            let player_index = floors.neighbor(new_box_index, -direction).unwrap();
+                            let next_box_index = floors.neighbor(new_box_index, direction).unwrap();
+                            new_moves += state.player_distances(floors)[player_index] + 1;
+                            state.move_box(new_box_index, next_box_index, floors);
+                            state.player_position = floors.position(new_box_index);
+                            new_player_index = new_box_index;
+                            new_box_index = next_box_index;
+                            new_pushes += 1;
+                        }
+                    }
+                }
+
+                let mut new_secondary_values = self.secondary_values;
+                if let Some(player_walks) = &player_walks {
+                    let path = player_walks
+                        .path(player_index, push_direction, floors)
+                        .unwrap();
+                    for direction in path {
+                        new_secondary_values.count(Action::Move(direction));
+                    }
+                    for _ in self.pushes..new_pushes {
+                        new_secondary_values.count(Action::Push(push_direction));
+                    }
+                }
+
+                let mut new_state = self.state.clone();
+                new_state.move_box(box_index, new_box_index, floors);
+                new_state.player_position = floors.position(new_player_index);
+
+                // Skip pushes into the goal room out of the packing order
+                if let Some(packing_order) = packing_order {
+                    if packing_order.contains(new_box_index)
+                        && !packing_order.is_followed_by(new_state.boxes())
+                    {
+                        continue;
+                    }
+                }
 
                 // Skip freeze deadlocks
-                if !solver.map()[new_box_position].intersects(Tiles::Goal)
-                    && is_freeze_deadlock(
+                let new_box_position = floors.position(new_box_index);
+                let has_box = |position| new_state.has_box(position, floors);
+                let is_frozen = (config.freeze_deadlocks() || config.frozen_corral_deadlocks())
+                    && is_freeze_deadlock_by(
                         solver.map(),
                         new_box_position,
-                        &new_box_positions,
+                        &has_box,
                         &mut HashSet::new(),
+                    );
+                if config.freeze_deadlocks()
+                    && is_frozen
+                    && !solver.map()[new_box_position].intersects(Tiles::Goal)
+                {
+                    solver.record_stats(|stats| stats.deadlocks_pruned += 1);
+                    continue;
+                }
+
+                // Skip closed diagonal deadlocks
+                if config.closed_diagonal_deadlocks()
+                    && is_closed_diagonal_deadlock_by(
+                        solver.map(),
+                        new_box_position,
+                        &has_box,
+                        new_state.player_position,
+                    )
+                {
+                    solver.record_stats(|stats| stats.deadlocks_pruned += 1);
+                    continue;
+                }
+
+                // Skip areas enclosed by frozen boxes with fewer goals than
+                // boxes, which can only be enclosed by the pushed box if it is
+                // frozen
+                if config.frozen_corral_deadlocks()
+                    && is_frozen
+                    && is_frozen_corral_deadlock_by(
+                        solver.map(),
+                        new_state.box_positions(floors),
+                        &has_box,
+                        new_state.player_position,
                     )
                 {
+                    solver.record_stats(|stats| stats.deadlocks_pruned += 1);
                     continue;
                 }
 
-                successors.push(Node::new(
-                    State {
-                        player_position: new_player_position,
-                        box_positions: new_box_positions,
-                    },
+                // Skip deadlock patterns
+                if config.uses_deadlock_patterns()
+                    && DeadlockPatterns::builtin().is_deadlock(
+                        solver.map(),
+                        new_box_position,
+                        has_box,
+                        new_state.player_position,
+                    )
+                {
+                    solver.record_stats(|stats| stats.deadlocks_pruned += 1);
+                    continue;
+                }
+
+                // Skip bipartite deadlocks, which are already detected by the
+                // minimum matching heuristic. The boxes could be matched
+                // before, so only pushes losing reachable goals are checked
+                if config.bipartite_deadlocks() && config.heuristic() == Heuristic::NearestGoal {
+                    let push_distances = solver.push_distances();
+                    let reachable_goals = |index| {
+                        push_distances
+                            .reachable_goals(floors.position(index))
+                            .count()
+                    };
+                    if reachable_goals(new_box_index) < reachable_goals(box_index)
+                        && is_bipartite_deadlock_by(push_distances, new_state.box_positions(floors))
+                    {
+                        solver.record_stats(|stats| stats.deadlocks_pruned += 1);
+                        continue;
+                    }
+                }
+
+                // Skip learned deadlocks
+                if solver.is_learned_deadlock(&new_state, new_box_index) {
+                    solver.record_stats(|stats| stats.deadlocks_pruned += 1);
+                    continue;
+                }
+
+                let mut new_matching = self.matching.clone();
+                if let Some(matching) = &mut new_matching {
+                    matching.push(box_index, new_box_index, solver);
+                }
+
+                let successor = Node::with_matching(
+                    new_state,
                     new_pushes,
                     new_moves,
+                    new_secondary_values,
+                    new_matching,
                     solver,
-                ));
+                );
+                if successor.is_deadlock() {
+                    solver.record_stats(|stats| stats.deadlocks_pruned += 1);
+                    continue;
+                }
+                successors.push(successor);
             }
         }
         successors
@@ -127,7 +419,7 @@ impl PartialEq for Node {
 
 impl Ord for Node {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.priority.cmp(&other.priority).reverse()
+        self.priority().cmp(&other.priority()).reverse()
     }
 }
 
@@ -136,3 +428,101 @@ impl PartialOrd for Node {
         Some(self.cmp(other))
     }
 }
+
+/// A node in the reverse search tree, where boxes are pulled from the goals
+/// back to their initial positions.
+#[derive(Clone, Eq, Debug)]
+pub struct PullNode {
+    pub state: State,
+    pub pulls: i32,
+    /// The hash of the normalized state.
+    pub hash: u64,
+    heuristic: i32,
+    priority: i32,
+}
+
+impl PullNode {
+    /// Creates a new `PullNode`, or returns `None` if some box can not be
+    /// pulled to any initial box position.
+    pub fn new(state: State, pulls: i32, solver: &Solver) -> Option<Self> {
+        let pull_distances = solver.pull_distances();
+        let heuristic = state
+            .box_positions(solver.floors())
+            .map(|box_position| pull_distances.get_nearest(box_position))
+            .sum::<Option<i32>>()?;
+        let priority = match solver.strategy() {
+            Strategy::Fast => heuristic,
+            _ => pulls + heuristic,
+        };
+        Some(Self {
+            hash: state.normalized_hash(solver.floors()),
+            state,
+            pulls,
+            heuristic,
+            priority,
+        })
+    }
+
+    /// Returns the successors of the node.
+    pub fn successors(&self, solver: &Solver) -> Vec<PullNode> {
+        let mut successors = Vec::new();
+        let floors = solver.floors();
+        let player_distances = self.state.player_distances(floors);
+        // Creates successor states by pulling boxes
+        for box_index in self.state.boxes().iter() {
+            for pull_direction in Direction::iter() {
+                // Checks if the player can pull the box
+                let Some(new_box_index) = floors.neighbor(box_index, pull_direction) else {
+                    continue;
+                };
+                if player_distances[new_box_index] == i32::MAX {
+                    continue;
+                }
+                let Some(new_player_index) = floors.neighbor(new_box_index, pull_direction) else {
+                    continue;
+                };
+                if self.state.boxes().contains(new_player_index) {
+                    continue;
+                }
+
+                let mut new_state = self.state.clone();
+                new_state.move_box(box_index, new_box_index, floors);
+                new_state.player_position = floors.position(new_player_index);
+                match PullNode::new(new_state, self.pulls + 1, solver) {
+                    Some(successor) => successors.push(successor),
+                    None => solver.record_stats(|stats| stats.deadlocks_pruned += 1),
+                }
+            }
+        }
+        successors
+    }
+
+    /// Returns the lower bound of the pulls needed to reach the initial
+    /// state.
+    pub fn heuristic(&self) -> i32 {
+        self.heuristic
+    }
+
+    /// Returns the estimated total pulls of the solution through this node.
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+}
+
+impl PartialEq for PullNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.state == other.state
+    }
+}
+
+impl Ord for PullNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority().cmp(&other.priority()).reverse()
+    }
+}
+
+impl PartialOrd for PullNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
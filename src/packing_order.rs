@@ -0,0 +1,258 @@
+//! Packing order of boxes into a goal room.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::{
+    direction::Direction,
+    map::Map,
+    math::IVector2,
+    push_distances::PushDistances,
+    state::{BitSet, Floors},
+};
+
+/// The order to fill the goals of a goal room.
+///
+/// A goal room is an area containing all goals, which is connected to the
+/// rest of the map through a single entrance cell. Boxes pushed into the room
+/// in the wrong order may block the goals behind them, so the order is found
+/// by pulling boxes out of the filled room one by one.
+#[derive(Clone, Debug)]
+pub struct PackingOrder {
+    entrance: usize,
+    room: BitSet,
+    /// The goals in the order they should be filled.
+    order: Vec<usize>,
+    floors: Floors,
+}
+
+impl PackingOrder {
+    /// Computes the packing order of the map.
+    ///
+    /// Returns `None` if the goals are not in a goal room without boxes, or no
+    /// box can be pulled out of the filled room in any order.
+    pub fn new(map: &Map) -> Option<Self> {
+        let floors = Floors::new(map);
+        let (entrance, room) = goal_room(map, &floors)?;
+        let mut instance = Self {
+            entrance,
+            room,
+            order: Vec::new(),
+            floors,
+        };
+
+        let mut remaining = instance.floors.goals().clone();
+        if !instance.unpack(&mut remaining, &mut HashSet::new()) {
+            return None;
+        }
+        instance.order.reverse();
+        Some(instance)
+    }
+
+    /// Returns the position of the entrance of the goal room.
+    pub fn entrance(&self) -> IVector2 {
+        self.floors.position(self.entrance)
+    }
+
+    /// Returns the positions of the goal room, excluding the entrance.
+    pub fn room(&self) -> impl Iterator<Item = IVector2> + '_ {
+        self.room.iter().map(|index| self.floors.position(index))
+    }
+
+    /// Returns the goal positions in the order they should be filled.
+    pub fn order(&self) -> impl Iterator<Item = IVector2> + '_ {
+        self.order.iter().map(|&index| self.floors.position(index))
+    }
+
+    /// Returns `true` if the boxes in the goal room follow the packing order.
+    ///
+    /// The boxes must fill a prefix of the order, except for at most one box
+    /// being pushed to the next goal.
+    pub(crate) fn is_followed_by(&self, boxes: &BitSet) -> bool {
+        let placed = self
+            .order
+            .iter()
+            .take_while(|&&goal| boxes.contains(goal))
+            .count();
+        let in_room = boxes
+            .iter()
+            .filter(|&index| self.room.contains(index))
+            .count();
+        in_room <= placed + 1
+    }
+
+    /// Returns the next goal of the order without a box, or `None` if all
+    /// goals are filled.
+    pub(crate) fn next_goal(&self, boxes: &BitSet) -> Option<usize> {
+        self.order
+            .iter()
+            .copied()
+            .find(|&goal| !boxes.contains(goal))
+    }
+
+    /// Returns the estimated cost to fill the goals in order, or `None` if a
+    /// box can not reach any goal.
+    ///
+    /// Boxes on the filled prefix of the order are done, and every other box
+    /// is estimated by the pushes to its nearest goal. Each goal of the order
+    /// which is not filled yet adds a constant cost, so the search is drawn to
+    /// fill the goals in order.
+    pub(crate) fn heuristic(&self, boxes: &BitSet, push_distances: &PushDistances) -> Option<i32> {
+        const UNFILLED_GOAL_COST: i32 = 50;
+
+        let mut unplaced = boxes.clone();
+        let mut unfilled = self.order.len() as i32;
+        for &goal in self.order.iter().take_while(|&&goal| boxes.contains(goal)) {
+            unplaced.remove(goal);
+            unfilled -= 1;
+        }
+        let mut sum = unfilled * UNFILLED_GOAL_COST;
+        for index in unplaced.iter() {
+            sum += push_distances.get_nearest(self.floors.position(index))?;
+        }
+        Some(sum)
+    }
+
+    /// Returns `true` if the floor index is the entrance of the goal room.
+    pub(crate) fn is_entrance(&self, index: usize) -> bool {
+        index == self.entrance
+    }
+
+    /// Returns `true` if the floor index is in the goal room.
+    pub(crate) fn contains(&self, index: usize) -> bool {
+        self.room.contains(index)
+    }
+
+    /// Pulls the boxes on the remaining goals out of the room, appending the
+    /// goals to the order in the order they are emptied.
+    fn unpack(&mut self, remaining: &mut BitSet, failed: &mut HashSet<BitSet>) -> bool {
+        if remaining.iter().next().is_none() {
+            return true;
+        }
+        if failed.contains(remaining) {
+            return false;
+        }
+        let goals: Vec<usize> = remaining.iter().collect();
+        for goal in goals {
+            remaining.remove(goal);
+            if self.can_pull_out(goal, remaining) {
+                self.order.push(goal);
+                if self.unpack(remaining, failed) {
+                    return true;
+                }
+                self.order.pop();
+            }
+            remaining.insert(goal);
+        }
+        failed.insert(remaining.clone());
+        false
+    }
+
+    /// Returns `true` if the box on the goal can be pulled to the entrance by
+    /// the player coming from outside the room, with the other boxes fixed.
+    fn can_pull_out(&self, goal: usize, boxes: &BitSet) -> bool {
+        let floors = &self.floors;
+        let outside: Vec<usize> = Direction::iter()
+            .filter_map(|direction| floors.neighbor(self.entrance, direction))
+            .filter(|&index| !self.room.contains(index))
+            .collect();
+        let initial_region = self.player_region(goal, &outside, boxes);
+        let mut visited = HashSet::from([(goal, initial_region.iter().next())]);
+        let mut queue = VecDeque::from([(goal, initial_region)]);
+        while let Some((box_index, region)) = queue.pop_front() {
+            if box_index == self.entrance && outside.iter().any(|&index| region.contains(index)) {
+                return true;
+            }
+            for direction in Direction::iter() {
+                let Some(new_box_index) = floors.neighbor(box_index, direction) else {
+                    continue;
+                };
+                if !region.contains(new_box_index) {
+                    continue;
+                }
+                let Some(new_player_index) = floors.neighbor(new_box_index, direction) else {
+                    continue;
+                };
+                if boxes.contains(new_player_index) {
+                    continue;
+                }
+                let new_region = self.player_region(new_box_index, &[new_player_index], boxes);
+                if visited.insert((new_box_index, new_region.iter().next())) {
+                    queue.push_back((new_box_index, new_region));
+                }
+            }
+        }
+        false
+    }
+
+    /// Returns the floor cells reachable by the player from the starting
+    /// cells, with the pulled box and the other boxes as obstacles.
+    fn player_region(&self, box_index: usize, starts: &[usize], boxes: &BitSet) -> BitSet {
+        let floors = &self.floors;
+        let mut region = BitSet::new(floors.len());
+        let mut stack = Vec::new();
+        for &start in starts {
+            if start != box_index && !boxes.contains(start) && !region.contains(start) {
+                region.insert(start);
+                stack.push(start);
+            }
+        }
+        while let Some(index) = stack.pop() {
+            for direction in Direction::iter() {
+                let Some(neighbor) = floors.neighbor(index, direction) else {
+                    continue;
+                };
+                if neighbor == box_index || boxes.contains(neighbor) || region.contains(neighbor) {
+                    continue;
+                }
+                region.insert(neighbor);
+                stack.push(neighbor);
+            }
+        }
+        region
+    }
+}
+
+/// Returns the entrance and the cells of the smallest goal room of the map.
+///
+/// The entrance must not be a goal, and the room must not contain boxes.
+fn goal_room(map: &Map, floors: &Floors) -> Option<(usize, BitSet)> {
+    let goals = floors.goals();
+    let first_goal = goals.iter().next()?;
+    let boxes: Vec<usize> = map
+        .box_positions()
+        .iter()
+        .map(|box_position| floors.index(*box_position).expect("box is not on floor"))
+        .collect();
+
+    let mut best: Option<(usize, usize, BitSet)> = None;
+    for entrance in (0..floors.len()).filter(|&index| !goals.contains(index)) {
+        // Flood fill from a goal without passing through the entrance
+        let mut room = BitSet::new(floors.len());
+        room.insert(first_goal);
+        let mut stack = vec![first_goal];
+        let mut size = 1;
+        while let Some(index) = stack.pop() {
+            for direction in Direction::iter() {
+                let Some(neighbor) = floors.neighbor(index, direction) else {
+                    continue;
+                };
+                if neighbor == entrance || room.contains(neighbor) {
+                    continue;
+                }
+                room.insert(neighbor);
+                stack.push(neighbor);
+                size += 1;
+            }
+        }
+        if size + 1 == floors.len()
+            || goals.iter().any(|goal| !room.contains(goal))
+            || boxes.iter().any(|&index| room.contains(index))
+        {
+            continue;
+        }
+        if best.as_ref().is_none_or(|(min_size, ..)| size < *min_size) {
+            best = Some((size, entrance, room));
+        }
+    }
+    best.map(|(_, entrance, room)| (entrance, room))
+}
@@ -0,0 +1,243 @@
+//! Distances of pushing a lone box to each goal.
+
+use std::collections::VecDeque;
+
+use crate::{direction::Direction, map::Map, math::IVector2, state::Floors};
+
+/// Marks a side of a box which is not a floor cell.
+const NO_REGION: u8 = u8::MAX;
+
+/// The minimum number of pushes to push a lone box from each floor cell to
+/// each goal.
+///
+/// Distances depend on the side of the box the player is on, since the box
+/// splits the area around it into regions the player can not move between.
+/// Other boxes are ignored, so the distances are lower bounds for states with
+/// multiple boxes.
+#[derive(Clone, Debug)]
+pub struct PushDistances {
+    floors: Floors,
+    /// The goal number of each floor cell.
+    goals: Vec<Option<usize>>,
+    /// The distances indexed by goal, floor cell and side of the player.
+    distances: Vec<i32>,
+    /// The distances indexed by goal and floor cell, from any side.
+    min_distances: Vec<i32>,
+    /// The player regions of the sides of each floor cell.
+    regions: Vec<[u8; 4]>,
+}
+
+impl PushDistances {
+    /// Calculates the push distances of the map.
+    ///
+    /// A box is placed on each goal, then all the positions the box can be
+    /// pulled to are searched in breadth-first order.
+    pub fn new(map: &Map) -> Self {
+        let floors = Floors::new(map);
+        let goal_indices = floors.goals().iter().collect();
+        Self::with_goals(floors, goal_indices, false)
+    }
+
+    /// Calculates the minimum number of pulls to pull a lone box from each
+    /// floor cell to each box position of the map.
+    ///
+    /// The box positions take the place of the goals, and the box is pushed
+    /// away from them instead of pulled.
+    pub(crate) fn new_pull(map: &Map) -> Self {
+        let floors = Floors::new(map);
+        let mut box_indices: Vec<usize> = map
+            .box_positions()
+            .iter()
+            .map(|box_position| floors.index(*box_position).expect("box is not on floor"))
+            .collect();
+        box_indices.sort_unstable();
+        Self::with_goals(floors, box_indices, true)
+    }
+
+    fn with_goals(floors: Floors, goal_indices: Vec<usize>, pull: bool) -> Self {
+        let regions = side_regions(&floors);
+
+        let mut goals = vec![None; floors.len()];
+        for (goal, &goal_index) in goal_indices.iter().enumerate() {
+            goals[goal_index] = Some(goal);
+        }
+
+        let stride = floors.len() * 4;
+        let mut distances = vec![i32::MAX; goal_indices.len() * stride];
+        for (goal, &goal_index) in goal_indices.iter().enumerate() {
+            let distances = &mut distances[goal * stride..(goal + 1) * stride];
+            let mut queue = VecDeque::new();
+            for side in Direction::iter() {
+                distances[goal_index * 4 + side as usize] = 0;
+                if floors.neighbor(goal_index, side).is_some() {
+                    queue.push_back((goal_index, side));
+                }
+            }
+            while let Some((box_index, side)) = queue.pop_front() {
+                let distance = distances[box_index * 4 + side as usize];
+                for direction in Direction::iter() {
+                    // When pulling, the player must reach the cell the box is
+                    // pulled to and step back behind it. When pushing, the
+                    // player must reach the cell behind the box.
+                    let player_side = if pull { -direction } else { direction };
+                    if regions[box_index][player_side as usize] != regions[box_index][side as usize]
+                    {
+                        continue;
+                    }
+                    let Some(new_box_index) = floors.neighbor(box_index, direction) else {
+                        continue;
+                    };
+                    if !pull && floors.neighbor(new_box_index, direction).is_none() {
+                        continue;
+                    }
+                    let entry = &mut distances[new_box_index * 4 + player_side as usize];
+                    if *entry == i32::MAX {
+                        *entry = distance + 1;
+                        queue.push_back((new_box_index, player_side));
+                    }
+                }
+            }
+        }
+
+        let min_distances = distances
+            .chunks(4)
+            .map(|sides| *sides.iter().min().unwrap())
+            .collect();
+        Self {
+            floors,
+            goals,
+            distances,
+            min_distances,
+            regions,
+        }
+    }
+
+    /// Returns the minimum number of pushes to push the box to the goal, or
+    /// `None` if the goal is unreachable.
+    pub fn get(&self, box_position: IVector2, goal_position: IVector2) -> Option<i32> {
+        let box_index = self.floors.index(box_position)?;
+        let goal = self.goals[self.floors.index(goal_position)?]?;
+        Some(self.by_index(goal, box_index)).filter(|&distance| distance != i32::MAX)
+    }
+
+    /// Returns the minimum number of pushes to push the box to the goal, with
+    /// the player next to the box on the specified side, or `None` if the goal
+    /// is unreachable.
+    pub fn get_from_side(
+        &self,
+        box_position: IVector2,
+        player_side: Direction,
+        goal_position: IVector2,
+    ) -> Option<i32> {
+        let box_index = self.floors.index(box_position)?;
+        let goal = self.goals[self.floors.index(goal_position)?]?;
+        // The player can move to any side in the same region before pushing
+        let regions = self.regions[box_index];
+        let region = regions[player_side as usize];
+        Direction::iter()
+            .filter(|&side| {
+                side == player_side || (region != NO_REGION && regions[side as usize] == region)
+            })
+            .map(|side| self.distances[(goal * self.floors.len() + box_index) * 4 + side as usize])
+            .min()
+            .filter(|&distance| distance != i32::MAX)
+    }
+
+    /// Returns `true` if the player can move between the two sides of the box
+    /// without pushing it.
+    pub(crate) fn connects_sides(
+        &self,
+        box_position: IVector2,
+        side: Direction,
+        other_side: Direction,
+    ) -> bool {
+        self.floors.index(box_position).is_some_and(|box_index| {
+            let regions = self.regions[box_index];
+            regions[side as usize] != NO_REGION
+                && regions[side as usize] == regions[other_side as usize]
+        })
+    }
+
+    /// Returns the minimum number of pushes to push the box to the nearest
+    /// goal, or `None` if no goal is reachable.
+    pub fn get_nearest(&self, box_position: IVector2) -> Option<i32> {
+        let box_index = self.floors.index(box_position)?;
+        (0..self.goal_count())
+            .map(|goal| self.by_index(goal, box_index))
+            .min()
+            .filter(|&distance| distance != i32::MAX)
+    }
+
+    /// Returns the numbers of the goals the box can be pushed to, where goals
+    /// are numbered in the order of their floor indices.
+    pub(crate) fn reachable_goals(
+        &self,
+        box_position: IVector2,
+    ) -> impl Iterator<Item = usize> + '_ {
+        let box_index = self.floors.index(box_position);
+        (0..self.goal_count()).filter(move |&goal| {
+            box_index.is_some_and(|box_index| self.by_index(goal, box_index) != i32::MAX)
+        })
+    }
+
+    /// Returns the number of goals.
+    pub(crate) fn goal_count(&self) -> usize {
+        self.min_distances.len() / self.floors.len()
+    }
+
+    /// Returns the distance from the floor cell to the goal, where goals are
+    /// numbered in the order of their floor indices, or `i32::MAX` if the goal
+    /// is unreachable.
+    pub(crate) fn by_index(&self, goal: usize, box_index: usize) -> i32 {
+        self.min_distances[goal * self.floors.len() + box_index]
+    }
+}
+
+/// Returns the player regions of the sides of each floor cell occupied by a
+/// box.
+///
+/// Sides in the same region share the same label, which is the number of the
+/// first side in the region.
+fn side_regions(floors: &Floors) -> Vec<[u8; 4]> {
+    let mut visited = vec![usize::MAX; floors.len()];
+    (0..floors.len())
+        .map(|box_index| {
+            let mut regions = [NO_REGION; 4];
+            for side in Direction::iter() {
+                let Some(start) = floors.neighbor(box_index, side) else {
+                    continue;
+                };
+                if regions[side as usize] != NO_REGION {
+                    continue;
+                }
+                regions[side as usize] = side as u8;
+
+                // Flood fill the region, marking cells with the side number
+                let mark = box_index * 4 + side as usize;
+                visited[start] = mark;
+                let mut stack = vec![start];
+                while let Some(index) = stack.pop() {
+                    for direction in Direction::iter() {
+                        let Some(neighbor) = floors.neighbor(index, direction) else {
+                            continue;
+                        };
+                        if neighbor == box_index || visited[neighbor] == mark {
+                            continue;
+                        }
+                        visited[neighbor] = mark;
+                        stack.push(neighbor);
+                    }
+                }
+                for other_side in Direction::iter() {
+                    if floors
+                        .neighbor(box_index, other_side)
+                        .is_some_and(|neighbor| visited[neighbor] == mark)
+                    {
+                        regions[other_side as usize] = side as u8;
+                    }
+                }
+            }
+            regions
+        })
+        .collect()
+}
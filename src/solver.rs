@@ -1,20 +1,29 @@
 //! A solver for the Sokoban problem.
 
 use std::{
-    cell::OnceCell,
-    collections::{BinaryHeap, HashMap, HashSet},
-    time::Duration,
+    cell::{Cell, OnceCell, RefCell},
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
-use itertools::Itertools;
-
 use crate::{
+    actions::SecondaryValuesCounter,
     direction::Direction,
     math::IVector2,
-    node::Node,
-    path_finding::{find_path, reachable_area},
-    state::State,
-    Action, Actions, Map, SearchError, Tiles,
+    node::{Node, Priority, PullNode},
+    packing_order::PackingOrder,
+    path_finding::player_move_path,
+    push_distances::PushDistances,
+    state::{BitSet, Floors, PlayerWalks, State},
+    tunnels::Tunnels,
+    Action, Actions, Level, Map, SearchError, TerminationReason, VerifyError,
 };
 
 /// The strategy to use when searching for a solution.
@@ -27,8 +36,303 @@ pub enum Strategy {
     /// Find the push optimal solution
     OptimalPush,
 
-    /// Find the move optimal solution
+    /// Find the move optimal solution, breaking ties by pushes
     OptimalMove,
+
+    /// Find the optimal solution by moves, pushes, box lines, box changes,
+    /// pushing sessions and player lines, in that order
+    OptimalMovePush,
+
+    /// Find the optimal solution by pushes, moves, box lines, box changes,
+    /// pushing sessions and player lines, in that order
+    OptimalPushMove,
+}
+
+impl Strategy {
+    /// Returns `true` if the strategy minimizes the secondary values.
+    pub(crate) fn minimizes_secondary_values(self) -> bool {
+        matches!(self, Self::OptimalMovePush | Self::OptimalPushMove)
+    }
+}
+
+/// The heuristic used to estimate the pushes needed to solve a state.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum Heuristic {
+    /// Sum the pushes of each box to its nearest goal
+    #[default]
+    NearestGoal,
+
+    /// Sum the pushes of a minimum cost perfect matching between boxes and
+    /// goals, so that no two boxes target the same goal
+    MinimumMatching,
+}
+
+/// The optimizations used by a [`Solver`].
+///
+/// Each optimization can be toggled to measure its effect, such as in ablation
+/// studies. [`Solver::new`] uses the default configuration.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct SolverConfig {
+    heuristic: Heuristic,
+    tunnel_macros: bool,
+    freeze_deadlocks: bool,
+    closed_diagonal_deadlocks: bool,
+    frozen_corral_deadlocks: bool,
+    bipartite_deadlocks: bool,
+    static_deadlocks: bool,
+    normalization: bool,
+    corral_pruning: bool,
+    follows_packing_order: bool,
+    goal_macros: bool,
+    uses_deadlock_patterns: bool,
+    deadlock_learning_capacity: usize,
+}
+
+impl SolverConfig {
+    /// Creates a new `SolverConfig` with the default optimizations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the heuristic. Defaults to [`Heuristic::NearestGoal`].
+    pub fn with_heuristic(mut self, heuristic: Heuristic) -> Self {
+        self.heuristic = heuristic;
+        self
+    }
+
+    /// Returns the heuristic.
+    pub fn heuristic(&self) -> Heuristic {
+        self.heuristic
+    }
+
+    /// Sets whether to push boxes through tunnels in a single successor, and
+    /// skip pushes into tunnels against their push directions. Enabled by
+    /// default.
+    pub fn with_tunnel_macros(mut self, tunnel_macros: bool) -> Self {
+        self.tunnel_macros = tunnel_macros;
+        self
+    }
+
+    /// Returns `true` if boxes are pushed through tunnels in a single
+    /// successor.
+    pub fn tunnel_macros(&self) -> bool {
+        self.tunnel_macros
+    }
+
+    /// Sets whether to prune pushes creating freeze deadlocks. Enabled by
+    /// default.
+    pub fn with_freeze_deadlocks(mut self, freeze_deadlocks: bool) -> Self {
+        self.freeze_deadlocks = freeze_deadlocks;
+        self
+    }
+
+    /// Returns `true` if pushes creating freeze deadlocks are pruned.
+    pub fn freeze_deadlocks(&self) -> bool {
+        self.freeze_deadlocks
+    }
+
+    /// Sets whether to prune pushes creating [closed diagonal
+    /// deadlocks](crate::deadlock::is_closed_diagonal_deadlock). Enabled by
+    /// default.
+    pub fn with_closed_diagonal_deadlocks(mut self, closed_diagonal_deadlocks: bool) -> Self {
+        self.closed_diagonal_deadlocks = closed_diagonal_deadlocks;
+        self
+    }
+
+    /// Returns `true` if pushes creating closed diagonal deadlocks are pruned.
+    pub fn closed_diagonal_deadlocks(&self) -> bool {
+        self.closed_diagonal_deadlocks
+    }
+
+    /// Sets whether to prune pushes freezing a box which encloses an area
+    /// with fewer goals than boxes, see
+    /// [`is_frozen_corral_deadlock`](crate::deadlock::is_frozen_corral_deadlock).
+    /// Enabled by default.
+    pub fn with_frozen_corral_deadlocks(mut self, frozen_corral_deadlocks: bool) -> Self {
+        self.frozen_corral_deadlocks = frozen_corral_deadlocks;
+        self
+    }
+
+    /// Returns `true` if pushes creating frozen corral deadlocks are pruned.
+    pub fn frozen_corral_deadlocks(&self) -> bool {
+        self.frozen_corral_deadlocks
+    }
+
+    /// Sets whether to prune pushes creating [bipartite
+    /// deadlocks](crate::deadlock::is_bipartite_deadlock).
+    ///
+    /// They are always detected by the [`Heuristic::MinimumMatching`]
+    /// heuristic, so it only applies to the [`Heuristic::NearestGoal`]
+    /// heuristic. Enabled by default.
+    pub fn with_bipartite_deadlocks(mut self, bipartite_deadlocks: bool) -> Self {
+        self.bipartite_deadlocks = bipartite_deadlocks;
+        self
+    }
+
+    /// Returns `true` if pushes creating bipartite deadlocks are pruned.
+    pub fn bipartite_deadlocks(&self) -> bool {
+        self.bipartite_deadlocks
+    }
+
+    /// Sets whether to prune pushes to dead squares before creating the
+    /// successors. Otherwise they are only pruned by the heuristic. Enabled by
+    /// default.
+    pub fn with_static_deadlocks(mut self, static_deadlocks: bool) -> Self {
+        self.static_deadlocks = static_deadlocks;
+        self
+    }
+
+    /// Returns `true` if pushes to dead squares are pruned.
+    pub fn static_deadlocks(&self) -> bool {
+        self.static_deadlocks
+    }
+
+    /// Sets whether states only differing in the player position within the
+    /// same area are treated as duplicates, with the [`Strategy::Fast`] and
+    /// [`Strategy::OptimalPush`] strategies. Enabled by default.
+    pub fn with_normalization(mut self, normalization: bool) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Returns `true` if states are normalized by the area of the player.
+    pub fn normalization(&self) -> bool {
+        self.normalization
+    }
+
+    /// Sets whether to prune successors with player-inaccessible corrals
+    /// (PI-corrals).
+    ///
+    /// When the player can not reach an area enclosed by boxes which can only
+    /// be pushed into it, only the pushes of these boxes are generated, and
+    /// the state is pruned if the boxes can not be pushed to goals. The
+    /// successors are only restricted with the [`Strategy::Fast`] and
+    /// [`Strategy::OptimalPush`] strategies, since the restriction may
+    /// increase the number of moves. Enabled by default.
+    pub fn with_corral_pruning(mut self, corral_pruning: bool) -> Self {
+        self.corral_pruning = corral_pruning;
+        self
+    }
+
+    /// Returns `true` if successors are pruned with PI-corrals.
+    pub fn corral_pruning(&self) -> bool {
+        self.corral_pruning
+    }
+
+    /// Sets whether to push boxes into the goal room in its [packing
+    /// order](PackingOrder).
+    ///
+    /// The heuristic is drawn to fill the goals in order, and pushes into the
+    /// goal room which break the packing order are pruned. Since the heuristic
+    /// is not admissible and some solutions may be pruned, it only applies to
+    /// the [`Strategy::Fast`] strategy. Disabled by default.
+    pub fn with_packing_order(mut self, follows_packing_order: bool) -> Self {
+        self.follows_packing_order = follows_packing_order;
+        self
+    }
+
+    /// Returns `true` if boxes are pushed into the goal room in its packing
+    /// order.
+    pub fn follows_packing_order(&self) -> bool {
+        self.follows_packing_order
+    }
+
+    /// Sets whether to use goal macros.
+    ///
+    /// Once a box is pushed onto the entrance of the goal room, it is pushed
+    /// on to the next goal of the [packing order](PackingOrder) in the same
+    /// successor, instead of generating every push inside the room. Since
+    /// some solutions may be pruned, it only applies to the
+    /// [`Strategy::Fast`] strategy. Disabled by default.
+    pub fn with_goal_macros(mut self, goal_macros: bool) -> Self {
+        self.goal_macros = goal_macros;
+        self
+    }
+
+    /// Returns `true` if boxes entering the goal room are pushed to their
+    /// goals in a single successor.
+    pub fn goal_macros(&self) -> bool {
+        self.goal_macros
+    }
+
+    /// Sets whether to prune pushes creating patterns of the builtin
+    /// [deadlock pattern database](crate::deadlock_patterns::DeadlockPatterns).
+    /// Enabled by default.
+    pub fn with_deadlock_patterns(mut self, uses_deadlock_patterns: bool) -> Self {
+        self.uses_deadlock_patterns = uses_deadlock_patterns;
+        self
+    }
+
+    /// Returns `true` if pushes creating deadlock patterns are pruned.
+    pub fn uses_deadlock_patterns(&self) -> bool {
+        self.uses_deadlock_patterns
+    }
+
+    /// Sets the maximum number of deadlocks learned from corral deadlocks.
+    ///
+    /// Learned deadlocks are kept across searches, and no more deadlocks are
+    /// learned once the capacity is reached. A capacity of `0` disables
+    /// learning. Defaults to 4096.
+    pub fn with_deadlock_learning(mut self, capacity: usize) -> Self {
+        self.deadlock_learning_capacity = capacity;
+        self
+    }
+
+    /// Returns the maximum number of deadlocks learned.
+    pub fn deadlock_learning_capacity(&self) -> usize {
+        self.deadlock_learning_capacity
+    }
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        Self {
+            heuristic: Heuristic::default(),
+            tunnel_macros: true,
+            freeze_deadlocks: true,
+            closed_diagonal_deadlocks: true,
+            frozen_corral_deadlocks: true,
+            bipartite_deadlocks: true,
+            static_deadlocks: true,
+            normalization: true,
+            corral_pruning: true,
+            follows_packing_order: false,
+            goal_macros: false,
+            uses_deadlock_patterns: true,
+            deadlock_learning_capacity: 4096,
+        }
+    }
+}
+
+/// The metric minimized by [`optimize`].
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Metric {
+    /// Minimize moves, breaking ties by pushes
+    Moves,
+
+    /// Minimize pushes, breaking ties by moves
+    Pushes,
+}
+
+impl Metric {
+    /// Returns the cheapest strategy finding solutions optimal in the primary
+    /// value of the metric.
+    fn strategy(self) -> Strategy {
+        match self {
+            Self::Moves => Strategy::OptimalMove,
+            Self::Pushes => Strategy::OptimalPush,
+        }
+    }
+
+    /// Returns the cost of the actions, compared lexicographically.
+    fn cost(self, actions: &[Action]) -> (usize, usize) {
+        let moves = actions.len();
+        let pushes = actions.iter().filter(|action| action.is_push()).count();
+        match self {
+            Self::Moves => (moves, pushes),
+            Self::Pushes => (pushes, moves),
+        }
+    }
 }
 
 /// A solver for the Sokoban problem.
@@ -36,13 +340,21 @@ pub enum Strategy {
 pub struct Solver {
     map: Map,
     strategy: Strategy,
+    config: SolverConfig,
+    floors: OnceCell<Floors>,
     lower_bounds: OnceCell<HashMap<IVector2, i32>>,
-    tunnels: OnceCell<HashSet<(IVector2, Direction)>>,
+    push_distances: OnceCell<PushDistances>,
+    pull_distances: OnceCell<PushDistances>,
+    packing_order: OnceCell<Option<PackingOrder>>,
+    tunnels: OnceCell<Tunnels>,
     terminator: Terminator,
+    progress: Option<Progress>,
+    stats: Cell<SearchStats>,
+    learned_deadlocks: RefCell<LearnedDeadlocks>,
 }
 
 /// How to terminate the search.
-#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub enum Terminator {
     /// Never terminate.
     #[default]
@@ -51,6 +363,13 @@ pub enum Terminator {
     Timeout(Duration),
     /// Terminate after a number of iterations.
     Iterations(u64),
+    /// Terminate after a number of nodes are generated, which bounds the
+    /// memory used by the search.
+    Nodes(u64),
+    /// Terminate once the flag is set, typically from another thread.
+    Cancelled(Arc<AtomicBool>),
+    /// Terminate once any of the terminators fires.
+    Any(Vec<Terminator>),
 }
 
 impl Terminator {
@@ -65,52 +384,239 @@ impl Terminator {
     pub fn new_duration_secs(secs: u64) -> Self {
         Self::Timeout(Duration::from_secs(secs))
     }
+
+    /// Creates a new `Terminator` that terminates once the flag is set to
+    /// `true`.
+    pub fn new_cancellation(flag: Arc<AtomicBool>) -> Self {
+        Self::Cancelled(flag)
+    }
+
+    /// Combines two terminators into one that terminates once either of them
+    /// fires.
+    pub fn or(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::None, terminator) | (terminator, Self::None) => terminator,
+            (Self::Any(mut terminators), Self::Any(others)) => {
+                terminators.extend(others);
+                Self::Any(terminators)
+            }
+            (Self::Any(mut terminators), terminator) => {
+                terminators.push(terminator);
+                Self::Any(terminators)
+            }
+            (terminator, Self::Any(mut terminators)) => {
+                terminators.insert(0, terminator);
+                Self::Any(terminators)
+            }
+            (terminator, other) => Self::Any(vec![terminator, other]),
+        }
+    }
 }
 
-struct TerminatorInner {
-    terminator: Terminator,
+impl PartialEq for Terminator {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::None, Self::None) => true,
+            (Self::Timeout(a), Self::Timeout(b)) => a == b,
+            (Self::Iterations(a), Self::Iterations(b)) => a == b,
+            (Self::Nodes(a), Self::Nodes(b)) => a == b,
+            (Self::Cancelled(a), Self::Cancelled(b)) => Arc::ptr_eq(a, b),
+            (Self::Any(a), Self::Any(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Terminator {}
+
+/// Statistics of a search.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub struct SearchStats {
+    /// The number of nodes whose successors were generated.
+    pub nodes_expanded: u64,
+    /// The number of successors generated.
+    pub nodes_generated: u64,
+    /// The number of successors skipped since their states were already
+    /// reached with a lower or equal cost.
+    pub duplicates_pruned: u64,
+    /// The number of successors skipped since their states are deadlocks.
+    pub deadlocks_pruned: u64,
+    /// The number of deadlocks learned.
+    pub deadlocks_learned: u64,
+    /// The maximum number of nodes in the open list.
+    pub peak_open_list: usize,
+    /// The time elapsed since the search started.
+    pub elapsed: Duration,
+    /// The lowest heuristic value of the expanded nodes.
+    pub best_heuristic: Option<i32>,
+}
+
+impl SearchStats {
+    fn record_expansion(&mut self, heuristic: i32, successors: usize) {
+        self.nodes_expanded += 1;
+        self.nodes_generated += successors as u64;
+        self.best_heuristic = Some(self.best_heuristic.map_or(heuristic, |h| h.min(heuristic)));
+    }
+
+    fn record_open_list(&mut self, len: usize) {
+        self.peak_open_list = self.peak_open_list.max(len);
+    }
+}
+
+/// A deadlock learned during the search.
+///
+/// A sub-search proved that the boxes can not be pushed to goals while the
+/// player is in the region, even with all other boxes removed. So any state
+/// containing these boxes, with the player in the region, is a deadlock.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct LearnedDeadlock {
+    /// The positions of the boxes.
+    pub box_positions: Vec<IVector2>,
+    /// The positions the player can be in, with only these boxes on the map.
+    pub player_region: Vec<IVector2>,
+    /// The number of states pruned by the deadlock.
+    pub pruned: u64,
+}
+
+/// The deadlocks learned during the search, indexed by their boxes.
+#[derive(Clone, Debug, Default)]
+struct LearnedDeadlocks {
+    deadlocks: Vec<(BitSet, BitSet, u64)>,
+    /// The deadlocks containing a box at each floor index.
+    by_box: HashMap<usize, Vec<usize>>,
+}
+
+/// A callback invoked periodically with the statistics of a running search.
+#[derive(Clone)]
+struct Progress {
+    interval: Duration,
+    callback: Arc<dyn Fn(&SearchStats) + Send + Sync>,
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Progress")
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The context of a running search, which terminates the search and reports
+/// its progress.
+struct SearchContext<'a> {
+    solver: &'a Solver,
     iterations: u64,
-    start_time: std::time::Instant,
+    start_time: Instant,
+    last_progress_time: Instant,
 }
 
-impl TerminatorInner {
-    fn new(terminator: Terminator) -> Self {
+impl<'a> SearchContext<'a> {
+    fn new(solver: &'a Solver) -> Self {
+        solver.stats.set(SearchStats::default());
+        let start_time = Instant::now();
         Self {
-            terminator,
+            solver,
             iterations: 0,
-            start_time: std::time::Instant::now(),
+            start_time,
+            last_progress_time: start_time,
         }
     }
 
-    fn tick(&mut self) -> bool {
+    /// Advances the search by one iteration, and returns the reason to
+    /// terminate the search if any.
+    fn tick(&mut self) -> Option<TerminationReason> {
         self.iterations += 1;
-        match self.terminator {
-            Terminator::None => false,
-            Terminator::Timeout(duration) => {
-                // Check timeout every 1000 iterations to avoid excessive CPU usage
-                self.iterations % 1000 == 0 && self.start_time.elapsed() >= duration
+        if let Some(progress) = &self.solver.progress {
+            if self.last_progress_time.elapsed() >= progress.interval {
+                self.last_progress_time = Instant::now();
+                self.report_progress();
+            }
+        }
+        self.check(&self.solver.terminator)
+    }
+
+    /// Returns the reason to terminate the search, or `None` if the search
+    /// should continue.
+    fn check(&self, terminator: &Terminator) -> Option<TerminationReason> {
+        match terminator {
+            Terminator::None => None,
+            Terminator::Timeout(duration) => (self.iterations.is_multiple_of(1000)
+                && self.start_time.elapsed() >= *duration)
+                .then_some(TerminationReason::Timeout),
+            Terminator::Iterations(max_iterations) => {
+                (self.iterations >= *max_iterations).then_some(TerminationReason::Iterations)
             }
-            Terminator::Iterations(max_iterations) => self.iterations >= max_iterations,
+            Terminator::Nodes(max_nodes) => (self.solver.stats.get().nodes_generated >= *max_nodes)
+                .then_some(TerminationReason::Nodes),
+            Terminator::Cancelled(flag) => flag
+                .load(Ordering::Relaxed)
+                .then_some(TerminationReason::Cancelled),
+            Terminator::Any(terminators) => terminators
+                .iter()
+                .find_map(|terminator| self.check(terminator)),
+        }
+    }
+
+    fn update_elapsed(&self) {
+        let elapsed = self.start_time.elapsed();
+        self.solver.record_stats(|stats| stats.elapsed = elapsed);
+    }
+
+    fn report_progress(&self) {
+        if let Some(progress) = &self.solver.progress {
+            self.update_elapsed();
+            (progress.callback)(&self.solver.stats.get());
         }
     }
 }
 
+impl Drop for SearchContext<'_> {
+    fn drop(&mut self) {
+        self.update_elapsed();
+        // Report the final statistics, so short searches are reported too
+        self.report_progress();
+    }
+}
+
+/// A search algorithm run by [`Solver::parallel_search`].
+#[derive(Clone, Copy, Debug)]
+enum Algorithm {
+    AStar,
+    Reverse,
+    Bidirectional,
+}
+
 /// Internal result type for IDA* search to distinguish between
 /// threshold updates and termination.
 enum IDAStarResult {
-    NewThreshold(i32),
-    Terminated,
+    /// The lowest estimated cost exceeding the threshold, or `None` if no
+    /// node was pruned by the threshold.
+    NewThreshold(Option<Priority>),
+    Terminated(TerminationReason),
 }
 
 impl Solver {
-    /// Creates a new `Solver`.
+    /// Creates a new `Solver` with the default optimizations.
     pub fn new(map: Map, strategy: Strategy) -> Self {
+        Self::with_config(map, strategy, SolverConfig::default())
+    }
+
+    /// Creates a new `Solver` with the optimizations of the configuration.
+    pub fn with_config(map: Map, strategy: Strategy, config: SolverConfig) -> Self {
         Self {
             map,
             strategy,
+            config,
+            floors: OnceCell::new(),
             lower_bounds: OnceCell::new(),
+            push_distances: OnceCell::new(),
+            pull_distances: OnceCell::new(),
+            packing_order: OnceCell::new(),
             tunnels: OnceCell::new(),
             terminator: Terminator::None,
+            progress: None,
+            stats: Cell::default(),
+            learned_deadlocks: RefCell::default(),
         }
     }
 
@@ -123,58 +629,174 @@ impl Solver {
     }
 
     /// Returns the terminator.
-    pub fn terminator(&self) -> Terminator {
-        self.terminator
+    pub fn terminator(&self) -> &Terminator {
+        &self.terminator
+    }
+
+    /// Sets a callback invoked periodically with the statistics of the
+    /// running search.
+    ///
+    /// The callback is invoked at most once per `interval` while the search
+    /// runs, and once more when it finishes.
+    pub fn with_progress(
+        mut self,
+        interval: Duration,
+        callback: impl Fn(&SearchStats) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Progress {
+            interval,
+            callback: Arc::new(callback),
+        });
+        self
+    }
+
+    /// Returns the statistics of the last search.
+    pub fn stats(&self) -> SearchStats {
+        self.stats.get()
+    }
+
+    /// Returns the configuration of the optimizations used by the solver.
+    pub fn config(&self) -> &SolverConfig {
+        &self.config
+    }
+
+    /// Returns the deadlocks learned so far.
+    pub fn learned_deadlocks(&self) -> Vec<LearnedDeadlock> {
+        let floors = self.floors();
+        self.learned_deadlocks
+            .borrow()
+            .deadlocks
+            .iter()
+            .map(|(boxes, player_region, pruned)| LearnedDeadlock {
+                box_positions: boxes.iter().map(|index| floors.position(index)).collect(),
+                player_region: player_region
+                    .iter()
+                    .map(|index| floors.position(index))
+                    .collect(),
+                pruned: *pruned,
+            })
+            .collect()
+    }
+
+    /// Records that the boxes can not be solved with the player in the region.
+    pub(crate) fn learn_deadlock(&self, boxes: BitSet, player_region: BitSet) {
+        let mut learned_deadlocks = self.learned_deadlocks.borrow_mut();
+        if learned_deadlocks.deadlocks.len() >= self.config.deadlock_learning_capacity {
+            return;
+        }
+        let id = learned_deadlocks.deadlocks.len();
+        for index in boxes.iter() {
+            learned_deadlocks.by_box.entry(index).or_default().push(id);
+        }
+        learned_deadlocks.deadlocks.push((boxes, player_region, 0));
+        self.record_stats(|stats| stats.deadlocks_learned += 1);
+    }
+
+    /// Returns `true` if the state contains a learned deadlock with the box at
+    /// the floor index.
+    pub(crate) fn is_learned_deadlock(&self, state: &State, box_index: usize) -> bool {
+        let mut learned_deadlocks = self.learned_deadlocks.borrow_mut();
+        let LearnedDeadlocks { deadlocks, by_box } = &mut *learned_deadlocks;
+        let Some(ids) = by_box.get(&box_index) else {
+            return false;
+        };
+        let player_index = self
+            .floors()
+            .index(state.player_position)
+            .expect("player is not on floor");
+        for &id in ids {
+            let (boxes, player_region, pruned) = &mut deadlocks[id];
+            if player_region.contains(player_index)
+                && boxes.iter().all(|index| state.boxes().contains(index))
+            {
+                *pruned += 1;
+                return true;
+            }
+        }
+        false
     }
 
     /// Searches for solution using the A* algorithm.
+    ///
+    /// With an optimal strategy, states reached again with a lower cost are
+    /// reopened, so the returned solution is optimal for the strategy.
     pub fn a_star_search(&self) -> Result<Actions, SearchError> {
+        let mut context = SearchContext::new(self);
         let mut heap = BinaryHeap::new();
         let mut came_from = HashMap::new();
-        let mut visited = HashSet::new();
-
-        let state: State = self.map.clone().into();
-        visited.insert(state.normalized_hash(&self.map));
-        heap.push(Node::new(state, 0, 0, self));
+        let mut costs = HashMap::new();
 
-        let mut terminator = TerminatorInner::new(self.terminator);
+        let state = State::from_map(&self.map, self.floors());
+        let node = Node::new(state, 0, 0, Default::default(), self);
+        if node.is_deadlock() {
+            return Err(SearchError::NoSolution);
+        }
+        costs.insert(node.hash, node.cost(self.strategy));
+        heap.push(node);
 
         while let Some(node) = heap.pop() {
-            if terminator.tick() {
-                return Err(SearchError::Terminated);
+            if let Some(reason) = context.tick() {
+                return Err(SearchError::Terminated(reason));
+            }
+
+            // Skip outdated nodes of states reached again with a lower cost
+            if costs[&node.hash] < node.cost(self.strategy) {
+                continue;
             }
 
             if node.state.is_solved(self) {
-                return Ok(self.construct_actions(node.state, &came_from));
+                return Ok(self.construct_actions(node.state, node.hash, &came_from));
             }
-            for successor in node.successors(self) {
-                if !visited.insert(successor.state.normalized_hash(&self.map)) {
-                    continue;
+            let successors = node.successors(self);
+            self.record_stats(|stats| stats.record_expansion(node.heuristic(), successors.len()));
+            for successor in successors {
+                let cost = successor.cost(self.strategy);
+                if let Some(&previous_cost) = costs.get(&successor.hash) {
+                    if self.strategy == Strategy::Fast || previous_cost <= cost {
+                        self.record_stats(|stats| stats.duplicates_pruned += 1);
+                        continue;
+                    }
                 }
-                came_from.insert(successor.state.clone(), node.state.clone());
+                costs.insert(successor.hash, cost);
+                came_from.insert(successor.hash, (node.hash, node.state.clone()));
                 heap.push(successor);
             }
+            self.record_stats(|stats| stats.record_open_list(heap.len()));
         }
         Err(SearchError::NoSolution)
     }
 
     /// Searches for solution using the IDA* algorithm.
-    pub fn ida_star_search(&self) -> Result<(), SearchError> {
-        let state: State = self.map.clone().into();
-        let mut threshold = state.heuristic(self);
-        let node = Node::new(state, 0, 0, self);
-
-        let mut terminator = TerminatorInner::new(self.terminator);
+    ///
+    /// Each iteration is a depth-first search bounded by the estimated total
+    /// cost of the nodes. The lowest cost each state is reached with in the
+    /// iteration is kept in a transposition table, so states are only searched
+    /// again if they are reached with a lower cost. With an optimal strategy,
+    /// the returned solution is optimal for the strategy.
+    pub fn ida_star_search(&self) -> Result<Actions, SearchError> {
+        let mut context = SearchContext::new(self);
+        let state = State::from_map(&self.map, self.floors());
+        let node = Node::new(state, 0, 0, Default::default(), self);
+        if node.is_deadlock() {
+            return Err(SearchError::NoSolution);
+        }
+        let mut threshold = self.estimated_cost(&node);
 
         loop {
-            match self.ida_star_search_inner(&node, threshold, &mut HashSet::new(), &mut terminator)
-            {
-                Ok(()) => return Ok(()),
-                Err(IDAStarResult::NewThreshold(t)) => threshold = t,
-                Err(IDAStarResult::Terminated) => return Err(SearchError::Terminated),
-            }
-            if threshold == i32::MAX {
-                return Err(SearchError::NoSolution);
+            let mut path = Vec::new();
+            match self.ida_star_search_inner(
+                &node,
+                threshold,
+                &mut path,
+                &mut HashMap::new(),
+                &mut context,
+            ) {
+                Ok(()) => return Ok(self.construct_actions_from_path(&path)),
+                Err(IDAStarResult::NewThreshold(Some(t))) => threshold = t,
+                Err(IDAStarResult::NewThreshold(None)) => return Err(SearchError::NoSolution),
+                Err(IDAStarResult::Terminated(reason)) => {
+                    return Err(SearchError::Terminated(reason))
+                }
             }
         }
     }
@@ -182,35 +804,349 @@ impl Solver {
     fn ida_star_search_inner(
         &self,
         node: &Node,
-        push_threshold: i32,
-        visited: &mut HashSet<u64>,
-        terminator: &mut TerminatorInner,
+        threshold: Priority,
+        path: &mut Vec<State>,
+        costs: &mut HashMap<u64, Priority>,
+        context: &mut SearchContext,
     ) -> Result<(), IDAStarResult> {
         // Check termination conditions
-        if terminator.tick() {
-            return Err(IDAStarResult::Terminated);
+        if let Some(reason) = context.tick() {
+            return Err(IDAStarResult::Terminated(reason));
         }
 
-        if !visited.insert(node.state.normalized_hash(&self.map)) {
-            return Err(IDAStarResult::NewThreshold(i32::MAX));
+        let estimated_cost = self.estimated_cost(node);
+        if estimated_cost > threshold {
+            return Err(IDAStarResult::NewThreshold(Some(estimated_cost)));
+        }
+        // Skip states already searched with a lower or equal cost
+        let cost = node.cost(self.strategy);
+        if costs
+            .get(&node.hash)
+            .is_some_and(|&previous_cost| previous_cost <= cost)
+        {
+            self.record_stats(|stats| stats.duplicates_pruned += 1);
+            return Err(IDAStarResult::NewThreshold(None));
         }
+        costs.insert(node.hash, cost);
+
+        path.push(node.state.clone());
+        // The open list of a depth-first search is the current path
+        self.record_stats(|stats| stats.record_open_list(path.len()));
         if node.state.is_solved(self) {
             return Ok(());
         }
-        if node.pushes > push_threshold {
-            return Err(IDAStarResult::NewThreshold(node.pushes));
-        }
-        let mut min_threshold = i32::MAX;
-        for successor in node.successors(self) {
-            match self.ida_star_search_inner(&successor, push_threshold, visited, terminator) {
+        let mut successors = node.successors(self);
+        self.record_stats(|stats| stats.record_expansion(node.heuristic(), successors.len()));
+        successors.sort_unstable_by_key(|successor| successor.priority());
+        let mut min_threshold = None;
+        for successor in &successors {
+            match self.ida_star_search_inner(successor, threshold, path, costs, context) {
                 Ok(()) => return Ok(()),
-                Err(IDAStarResult::NewThreshold(t)) => min_threshold = min_threshold.min(t),
-                Err(IDAStarResult::Terminated) => return Err(IDAStarResult::Terminated),
+                Err(IDAStarResult::NewThreshold(Some(t))) => {
+                    min_threshold = Some(min_threshold.map_or(t, |min: Priority| min.min(t)))
+                }
+                Err(IDAStarResult::NewThreshold(None)) => {}
+                Err(IDAStarResult::Terminated(reason)) => {
+                    return Err(IDAStarResult::Terminated(reason))
+                }
             }
         }
+        path.pop();
         Err(IDAStarResult::NewThreshold(min_threshold))
     }
 
+    /// Returns the estimated total cost of the solution through the node.
+    ///
+    /// Unlike the priority of the node, the cost of the path is included for
+    /// the [`Strategy::Fast`] strategy too.
+    fn estimated_cost(&self, node: &Node) -> Priority {
+        match self.strategy {
+            Strategy::Fast => {
+                let mut estimated_cost = node.cost(self.strategy);
+                estimated_cost[0] += node.priority()[0];
+                estimated_cost
+            }
+            _ => node.priority(),
+        }
+    }
+
+    /// Searches for solution by pulling boxes backward from the goals.
+    ///
+    /// The search starts from all boxes on goals, with the player in every
+    /// possible area, and ends when the boxes are pulled back to their initial
+    /// positions with the player in the area of the initial player position.
+    /// With an optimal strategy, the returned solution is push optimal.
+    pub fn reverse_search(&self) -> Result<Actions, SearchError> {
+        let mut context = SearchContext::new(self);
+        let floors = self.floors();
+        let initial_state = State::from_map(&self.map, floors);
+        let initial_hash = initial_state.normalized_hash(floors);
+
+        let mut heap = BinaryHeap::new();
+        let mut came_from = HashMap::new();
+        let mut costs = HashMap::new();
+        for node in self.pull_start_nodes() {
+            costs.insert(node.hash, node.pulls);
+            heap.push(node);
+        }
+
+        while let Some(node) = heap.pop() {
+            if let Some(reason) = context.tick() {
+                return Err(SearchError::Terminated(reason));
+            }
+
+            // Skip outdated nodes of states reached again with fewer pulls
+            if costs[&node.hash] < node.pulls {
+                continue;
+            }
+
+            if node.hash == initial_hash {
+                let mut path = Self::construct_path(node.state, node.hash, &came_from);
+                path.reverse();
+                path[0] = initial_state;
+                return Ok(self.construct_actions_from_path(&path));
+            }
+            let successors = node.successors(self);
+            self.record_stats(|stats| stats.record_expansion(node.heuristic(), successors.len()));
+            for successor in successors {
+                if let Some(&previous_pulls) = costs.get(&successor.hash) {
+                    if self.strategy == Strategy::Fast || previous_pulls <= successor.pulls {
+                        self.record_stats(|stats| stats.duplicates_pruned += 1);
+                        continue;
+                    }
+                }
+                costs.insert(successor.hash, successor.pulls);
+                came_from.insert(successor.hash, (node.hash, node.state.clone()));
+                heap.push(successor);
+            }
+            self.record_stats(|stats| stats.record_open_list(heap.len()));
+        }
+        Err(SearchError::NoSolution)
+    }
+
+    /// Searches for solution from both the initial state and the goals.
+    ///
+    /// A forward search pushing boxes and a [reverse
+    /// search](Self::reverse_search) pulling boxes are expanded in turn, until
+    /// they reach the same normalized state. The returned solution is not
+    /// necessarily optimal.
+    pub fn bidirectional_search(&self) -> Result<Actions, SearchError> {
+        let mut context = SearchContext::new(self);
+        let floors = self.floors();
+        let state = State::from_map(&self.map, floors);
+        let node = Node::new(state, 0, 0, Default::default(), self);
+        if node.is_deadlock() {
+            return Err(SearchError::NoSolution);
+        }
+
+        // The forward states by normalized hash, with their hashes
+        let mut forward_states = HashMap::new();
+        let mut forward_heap = BinaryHeap::new();
+        let mut forward_came_from = HashMap::new();
+        forward_states.insert(
+            node.state.normalized_hash(floors),
+            (node.hash, node.state.clone()),
+        );
+        forward_heap.push(node);
+
+        // The backward states by normalized hash
+        let mut backward_states = HashMap::new();
+        let mut backward_heap = BinaryHeap::new();
+        let mut backward_came_from = HashMap::new();
+        for node in self.pull_start_nodes() {
+            if let Some((hash, state)) = forward_states.get(&node.hash) {
+                return Ok(self.stitch_paths(
+                    Self::construct_path(state.clone(), *hash, &forward_came_from),
+                    Self::construct_path(node.state, node.hash, &backward_came_from),
+                ));
+            }
+            backward_states.insert(node.hash, node.state.clone());
+            backward_heap.push(node);
+        }
+
+        while !forward_heap.is_empty() && !backward_heap.is_empty() {
+            if let Some(reason) = context.tick() {
+                return Err(SearchError::Terminated(reason));
+            }
+
+            if forward_heap.len() <= backward_heap.len() {
+                let node = forward_heap.pop().unwrap();
+                let successors = node.successors(self);
+                self.record_stats(|stats| {
+                    stats.record_expansion(node.heuristic(), successors.len())
+                });
+                for successor in successors {
+                    let normalized_hash = successor.state.normalized_hash(floors);
+                    if forward_states.contains_key(&normalized_hash) {
+                        self.record_stats(|stats| stats.duplicates_pruned += 1);
+                        continue;
+                    }
+                    forward_came_from.insert(successor.hash, (node.hash, node.state.clone()));
+                    if let Some(state) = backward_states.get(&normalized_hash) {
+                        return Ok(self.stitch_paths(
+                            Self::construct_path(
+                                successor.state,
+                                successor.hash,
+                                &forward_came_from,
+                            ),
+                            Self::construct_path(
+                                state.clone(),
+                                normalized_hash,
+                                &backward_came_from,
+                            ),
+                        ));
+                    }
+                    forward_states
+                        .insert(normalized_hash, (successor.hash, successor.state.clone()));
+                    forward_heap.push(successor);
+                }
+            } else {
+                let node = backward_heap.pop().unwrap();
+                let successors = node.successors(self);
+                self.record_stats(|stats| {
+                    stats.record_expansion(node.heuristic(), successors.len())
+                });
+                for successor in successors {
+                    if backward_states.contains_key(&successor.hash) {
+                        self.record_stats(|stats| stats.duplicates_pruned += 1);
+                        continue;
+                    }
+                    backward_came_from.insert(successor.hash, (node.hash, node.state.clone()));
+                    if let Some((hash, state)) = forward_states.get(&successor.hash) {
+                        return Ok(self.stitch_paths(
+                            Self::construct_path(state.clone(), *hash, &forward_came_from),
+                            Self::construct_path(
+                                successor.state,
+                                successor.hash,
+                                &backward_came_from,
+                            ),
+                        ));
+                    }
+                    backward_states.insert(successor.hash, successor.state.clone());
+                    backward_heap.push(successor);
+                }
+            }
+            let open_list_len = forward_heap.len() + backward_heap.len();
+            self.record_stats(|stats| stats.record_open_list(open_list_len));
+        }
+        Err(SearchError::NoSolution)
+    }
+
+    /// Returns the start nodes of the reverse search, with all boxes on goals
+    /// and the player in each area separated by the boxes.
+    fn pull_start_nodes(&self) -> Vec<PullNode> {
+        let floors = self.floors();
+        let goals = floors.goals();
+        let mut nodes = Vec::new();
+        let mut visited = goals.clone();
+        for start in 0..floors.len() {
+            if visited.contains(start) {
+                continue;
+            }
+            let state = State::new(
+                floors.position(start),
+                goals.iter().map(|index| floors.position(index)),
+                floors,
+            );
+            for index in state
+                .player_distances(floors)
+                .into_iter()
+                .enumerate()
+                .filter_map(|(index, distance)| (distance != i32::MAX).then_some(index))
+            {
+                visited.insert(index);
+            }
+            nodes.extend(PullNode::new(state, 0, self));
+        }
+        nodes
+    }
+
+    /// Searches for solution with a portfolio of searches running in parallel
+    /// threads.
+    ///
+    /// Each thread runs a different search algorithm or heuristic on a clone
+    /// of the solver, and the first conclusive result cancels the others.
+    /// Only the searches which respect the strategy are run, so the returned
+    /// solution is as optimal as the one of [`a_star_search`]. At most
+    /// `threads` searches are run, and the progress callback is only invoked
+    /// by the first one.
+    ///
+    /// [`a_star_search`]: Self::a_star_search
+    pub fn parallel_search(&self, threads: usize) -> Result<Actions, SearchError> {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let mut portfolio = self.portfolio();
+        portfolio.truncate(threads.max(1));
+
+        let (result, stats) = thread::scope(|scope| {
+            let handles: Vec<_> = portfolio
+                .into_iter()
+                .enumerate()
+                .map(|(i, (algorithm, heuristic))| {
+                    let mut solver = self.clone().with_terminator(
+                        self.terminator
+                            .clone()
+                            .or(Terminator::Cancelled(cancelled.clone())),
+                    );
+                    solver.config = solver.config.with_heuristic(heuristic);
+                    if i > 0 {
+                        solver.progress = None;
+                    }
+                    let cancelled = &cancelled;
+                    scope.spawn(move || {
+                        let result = match algorithm {
+                            Algorithm::AStar => solver.a_star_search(),
+                            Algorithm::Reverse => solver.reverse_search(),
+                            Algorithm::Bidirectional => solver.bidirectional_search(),
+                        };
+                        if !matches!(result, Err(SearchError::Terminated(_))) {
+                            cancelled.store(true, Ordering::Relaxed);
+                        }
+                        (result, solver.stats())
+                    })
+                })
+                .collect();
+            let mut results: Vec<_> = handles
+                .into_iter()
+                .map(|handle| handle.join().expect("search thread panicked"))
+                .collect();
+            // Searches cancelled by a conclusive one report being terminated
+            let index = results
+                .iter()
+                .position(|(result, _)| !matches!(result, Err(SearchError::Terminated(_))))
+                .unwrap_or(0);
+            results.swap_remove(index)
+        });
+        self.stats.set(stats);
+        result
+    }
+
+    /// Returns the search algorithms and heuristics to run in parallel, in
+    /// order of preference.
+    fn portfolio(&self) -> Vec<(Algorithm, Heuristic)> {
+        let other_heuristic = match self.config.heuristic {
+            Heuristic::NearestGoal => Heuristic::MinimumMatching,
+            Heuristic::MinimumMatching => Heuristic::NearestGoal,
+        };
+        let mut portfolio = vec![
+            (Algorithm::AStar, self.config.heuristic),
+            (Algorithm::AStar, other_heuristic),
+        ];
+        if matches!(self.strategy, Strategy::Fast | Strategy::OptimalPush) {
+            portfolio.push((Algorithm::Reverse, self.config.heuristic));
+        }
+        if self.strategy == Strategy::Fast {
+            portfolio.push((Algorithm::Bidirectional, self.config.heuristic));
+        }
+        portfolio
+    }
+
+    /// Constructs the actions of a path of the forward search followed by a
+    /// path of the reverse search, which end at the same normalized state.
+    fn stitch_paths(&self, mut forward_path: Vec<State>, backward_path: Vec<State>) -> Actions {
+        forward_path.extend(backward_path.into_iter().rev().skip(1));
+        self.construct_actions_from_path(&forward_path)
+    }
+
     /// Returns a reference to the map.
     pub fn map(&self) -> &Map {
         &self.map
@@ -221,189 +1157,406 @@ impl Solver {
         self.strategy
     }
 
+    /// Updates the statistics of the running search.
+    pub(crate) fn record_stats(&self, f: impl FnOnce(&mut SearchStats)) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+
+    /// Returns the indexing of the floor cells of the map.
+    pub(crate) fn floors(&self) -> &Floors {
+        self.floors.get_or_init(|| Floors::new(&self.map))
+    }
+
+    /// Returns the hash identifying duplicate states.
+    ///
+    /// States are normalized unless the strategy minimizes moves, in which
+    /// case the exact player position matters for the remaining cost. If the
+    /// strategy minimizes the secondary values, the last action matters too.
+    pub(crate) fn state_hash(
+        &self,
+        state: &State,
+        secondary_values: &SecondaryValuesCounter,
+    ) -> u64 {
+        match self.strategy {
+            Strategy::Fast | Strategy::OptimalPush if self.config.normalization => {
+                state.normalized_hash(self.floors())
+            }
+            Strategy::Fast | Strategy::OptimalPush | Strategy::OptimalMove => {
+                state.hash(self.floors())
+            }
+            Strategy::OptimalMovePush | Strategy::OptimalPushMove => {
+                let last_action = match secondary_values.prev_action() {
+                    None => 0,
+                    Some(Action::Move(direction)) => 1 + direction as u64,
+                    Some(Action::Push(direction)) => 5 + direction as u64,
+                };
+                let key = last_action * 2 + secondary_values.has_prev_pushed_box() as u64;
+                state.hash(self.floors()) ^ key.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            }
+        }
+    }
+
     /// Returns a reference to the set of lower bounds.
+    ///
+    /// The lower bounds are the minimum number of pushes to push a box from a
+    /// position to the nearest goal. Since every push is also a move, they are
+    /// valid lower bounds for all strategies.
     pub fn lower_bounds(&self) -> &HashMap<IVector2, i32> {
-        // FIXME: Calculate lower bounds based on strategy
         self.lower_bounds.get_or_init(|| {
-            assert!(self.strategy == Strategy::OptimalPush || self.strategy == Strategy::Fast);
             let mut lower_bounds = self.calculate_minimum_push();
             lower_bounds.shrink_to_fit();
             lower_bounds
         })
     }
 
-    /// Returns a reference to the set of tunnels.
-    pub fn tunnels(&self) -> &HashSet<(IVector2, Direction)> {
-        self.tunnels.get_or_init(|| {
-            let mut tunnels = self.calculate_tunnels();
-            tunnels.shrink_to_fit();
-            tunnels
-        })
+    /// Returns a reference to the push distances of a lone box to each goal.
+    pub fn push_distances(&self) -> &PushDistances {
+        self.push_distances
+            .get_or_init(|| PushDistances::new(&self.map))
+    }
+
+    /// Returns the pull distances of a lone box to each initial box position.
+    pub(crate) fn pull_distances(&self) -> &PushDistances {
+        self.pull_distances
+            .get_or_init(|| PushDistances::new_pull(&self.map))
+    }
+
+    /// Returns the packing order of the goal room, or `None` if the map has no
+    /// goal room.
+    pub fn packing_order(&self) -> Option<&PackingOrder> {
+        self.packing_order
+            .get_or_init(|| PackingOrder::new(&self.map))
+            .as_ref()
+    }
+
+    /// Returns a reference to the tunnels of the map.
+    pub fn tunnels(&self) -> &Tunnels {
+        self.tunnels
+            .get_or_init(|| Tunnels::with_push_distances(&self.map, self.push_distances()))
     }
 
     /// Calculates and returns the minimum number of pushes to push the box to
     /// the nearest goal.
     fn calculate_minimum_push(&self) -> HashMap<IVector2, i32> {
+        let push_distances = self.push_distances();
         let mut lower_bounds = HashMap::new();
-        for goal_position in self.map.goal_positions() {
-            lower_bounds.insert(*goal_position, 0);
-
-            for pull_direction in Direction::iter() {
-                let new_box_position = goal_position + &pull_direction.into();
-                let new_player_position = new_box_position + &pull_direction.into();
-                if !self.map.in_bounds(new_player_position)
-                    || self.map[new_box_position].intersects(Tiles::Wall)
-                    || self.map[new_player_position].intersects(Tiles::Wall)
-                {
-                    continue;
+        for y in 0..self.map.dimensions().y {
+            for x in 0..self.map.dimensions().x {
+                let position = IVector2::new(x, y);
+                if let Some(distance) = push_distances.get_nearest(position) {
+                    lower_bounds.insert(position, distance);
                 }
-                self.calculate_minimum_push_to(
-                    *goal_position,
-                    new_player_position,
-                    &mut lower_bounds,
-                    &mut HashSet::new(),
-                );
-                break;
             }
         }
         lower_bounds
     }
 
-    /// Calculates the minimum push of the box to the specified position.
-    ///
-    /// Place the box on the goal, then calculate all the positions the box can
-    /// be pulled to and the minimum pulls it can be pulled to that position.
-    fn calculate_minimum_push_to(
+    /// Constructs the actions leading from the initial state to `state`.
+    fn construct_actions(
         &self,
-        box_position: IVector2,
-        player_position: IVector2,
-        lower_bounds: &mut HashMap<IVector2, i32>,
-        visited: &mut HashSet<(IVector2, Direction)>,
-    ) {
-        let player_reachable_area = reachable_area(player_position, |position| {
-            !(self.map[position].intersects(Tiles::Wall) || position == box_position)
-        });
-        for pull_direction in Direction::iter() {
-            let new_box_position = box_position + &pull_direction.into();
-            if self.map[new_box_position].intersects(Tiles::Wall) {
-                continue;
-            }
-            let new_player_position = new_box_position + &pull_direction.into();
-            if self.map[new_player_position].intersects(Tiles::Wall)
-                || !player_reachable_area.contains(&new_player_position)
-            {
-                continue;
-            }
+        state: State,
+        hash: u64,
+        came_from: &HashMap<u64, (u64, State)>,
+    ) -> Actions {
+        self.construct_actions_from_path(&Self::construct_path(state, hash, came_from))
+    }
 
-            let lower_bound = *lower_bounds.get(&new_box_position).unwrap_or(&i32::MAX);
-            if !visited.insert((new_box_position, pull_direction)) {
-                continue;
-            }
-            let new_lower_bound = lower_bounds[&box_position] + 1;
-            if new_lower_bound < lower_bound {
-                lower_bounds.insert(new_box_position, new_lower_bound);
+    /// Constructs the path of states leading from the first state of the
+    /// search to `state`.
+    fn construct_path(
+        state: State,
+        mut hash: u64,
+        came_from: &HashMap<u64, (u64, State)>,
+    ) -> Vec<State> {
+        let mut path = vec![state];
+        while let Some((previous_hash, previous_state)) = came_from.get(&hash) {
+            path.push(previous_state.clone());
+            hash = *previous_hash;
+        }
+        path.reverse();
+        path
+    }
+
+    /// Constructs the actions of a path of states, where each state differs
+    /// from the previous one by a box pushed in a straight line, or pushed on
+    /// to a goal by a goal macro.
+    fn construct_actions_from_path(&self, path: &[State]) -> Actions {
+        let floors = self.floors();
+        let mut actions = Actions::new();
+        let mut player_position = path[0].player_position;
+        for states in path.windows(2) {
+            let (previous_state, state) = (&states[0], &states[1]);
+
+            // Find the positions where the box was moved from and to
+            let previous_box_index = previous_state
+                .boxes()
+                .difference(state.boxes())
+                .next()
+                .unwrap();
+            let box_index = state
+                .boxes()
+                .difference(previous_state.boxes())
+                .next()
+                .unwrap();
+            let previous_box_position = floors.position(previous_box_index);
+            let box_position = floors.position(box_index);
+
+            // The box is pushed in a straight line, unless it was pushed to
+            // a goal by a goal macro
+            let mut walking_state = previous_state.clone();
+            walking_state.player_position = player_position;
+            let diff = box_position - previous_box_position;
+            let distance = (diff.x.abs() + diff.y.abs()) as usize;
+            let straight_direction = Direction::try_from(diff.signum())
+                .ok()
+                .filter(|&direction| {
+                    let mut index = previous_box_index;
+                    (0..distance).all(|_| match floors.neighbor(index, direction) {
+                        Some(next_index) if !previous_state.boxes().contains(next_index) => {
+                            index = next_index;
+                            true
+                        }
+                        _ => false,
+                    })
+                });
+            let push_directions = match straight_direction {
+                Some(direction) => vec![direction; distance],
+                None => {
+                    // Goal macros push the box in a straight line into the
+                    // entrance of the goal room, then on to the goal with the
+                    // fewest pushes from there, as in `Node::successors`
+                    let entrance_position = self.packing_order().unwrap().entrance();
+                    let entrance = floors.index(entrance_position).unwrap();
+                    let diff = entrance_position - previous_box_position;
+                    let direction = Direction::try_from(diff.signum()).unwrap();
+                    let mut push_directions =
+                        vec![direction; (diff.x.abs() + diff.y.abs()) as usize];
+                    let mut entrance_state = previous_state.clone();
+                    entrance_state.move_box(previous_box_index, entrance, floors);
+                    entrance_state.player_position = entrance_position - &direction.into();
+                    push_directions.extend(
+                        entrance_state
+                            .box_pushes(entrance, box_index, floors)
+                            .unwrap(),
+                    );
+                    push_directions
+                }
+            };
+
+            let mut box_index = previous_box_index;
+            for push_direction in push_directions {
+                // Find the path for the player to reach the box position before pushing it
+                let facing = actions.last().map(|action| action.direction());
+                let player_index = floors.neighbor(box_index, -push_direction).unwrap();
+                actions.extend(
+                    PlayerWalks::new(&walking_state, floors, facing)
+                        .path(player_index, push_direction, floors)
+                        .unwrap()
+                        .into_iter()
+                        .map(Action::Move),
+                );
+
+                // Push the box, including the pushes skipped through tunnels
+                actions.push(Action::Push(push_direction));
+                let new_box_index = floors.neighbor(box_index, push_direction).unwrap();
+                walking_state.move_box(box_index, new_box_index, floors);
+                walking_state.player_position = floors.position(box_index);
+                box_index = new_box_index;
             }
+            player_position = walking_state.player_position;
+        }
+        actions
+    }
+}
 
-            self.calculate_minimum_push_to(
-                new_box_position,
-                new_player_position,
-                lower_bounds,
-                visited,
-            );
+/// Shortens a solution of the map in the metric within the time limit.
+///
+/// The solution is improved by vicinity search, which searches the states
+/// around the solution path for a better solution. A state is in the vicinity
+/// if at most a few of its boxes are off the box positions of some state of
+/// the path. Each better solution becomes the new path, and once none is found
+/// the vicinity is widened by one box, until it spans all boxes and the
+/// solution is optimal, or the time runs out. The returned solution is never
+/// worse than the given one.
+pub fn optimize(
+    map: &Map,
+    solution: &Actions,
+    metric: Metric,
+    time_limit: Duration,
+) -> Result<Actions, VerifyError> {
+    map.verify_solution(solution)?;
+    let start_time = Instant::now();
+    let mut solution = minimize_moves(map, solution)?;
+    // The tables of the map are shared by all searches
+    let solver = Solver::new(map.clone(), metric.strategy());
+    let box_count = map.box_positions().len();
+    let mut displaced_boxes = 1;
+    while start_time.elapsed() < time_limit {
+        let remaining_time = time_limit.saturating_sub(start_time.elapsed());
+        match vicinity_search(&solver, &solution, displaced_boxes, metric, remaining_time) {
+            Some(optimized_solution) => solution = optimized_solution,
+            None if displaced_boxes >= box_count => break,
+            None => displaced_boxes += 1,
         }
     }
+    Ok(solution)
+}
 
-    /// Calculates and returns the set of tunnels.
-    ///
-    /// Tunnel is a common type of no influence push.
-    /// Since tunnels are only determined by the map terrain, they can be
-    /// pre-calculated.
-    fn calculate_tunnels(&self) -> HashSet<(IVector2, Direction)> {
-        let mut tunnels = HashSet::new();
-        for x in 1..self.map.dimensions().x - 1 {
-            for y in 1..self.map.dimensions().y - 1 {
-                let box_position = IVector2::new(x, y);
-                if !self.map[box_position].intersects(Tiles::Floor) {
+/// A node of the vicinity search, holding the state, the previous node and the
+/// push leading to the state, and the cost from the initial state.
+type VicinityNode = (State, Option<(usize, Direction)>, (usize, usize));
+
+/// Searches the states around the solution path for a better solution in the
+/// metric.
+///
+/// The search is an A* search over the states after each push, where states
+/// with more than `displaced_boxes` boxes off the box positions of every state
+/// of the path are skipped. Returns `None` if there is no better solution in
+/// the vicinity, or the time runs out.
+fn vicinity_search(
+    solver: &Solver,
+    solution: &Actions,
+    displaced_boxes: usize,
+    metric: Metric,
+    time_limit: Duration,
+) -> Option<Actions> {
+    let start_time = Instant::now();
+    let map = solver.map();
+    let floors = solver.floors();
+
+    // Collect the box sets along the solution path
+    let initial_state = State::from_map(map, floors);
+    let mut path = vec![initial_state.boxes().clone()];
+    let mut level = Level::from_map(map.clone());
+    for action in solution.iter() {
+        level.do_action(action.direction()).unwrap();
+        if action.is_push() {
+            path.push(State::from_map(level.map(), floors).boxes().clone());
+        }
+    }
+    let is_in_vicinity = |boxes: &BitSet| {
+        path.iter()
+            .any(|path_boxes| boxes.difference(path_boxes).nth(displaced_boxes).is_none())
+    };
+
+    // Each push moves a box a single cell, so the pushes left are at least
+    // the lower bound, and so are the moves
+    let heuristic = |state: &State| {
+        let lower_bound = state.heuristic(solver)? as usize;
+        Some((lower_bound, lower_bound))
+    };
+    let upper_bound = metric.cost(solution);
+
+    let mut nodes: Vec<VicinityNode> = vec![(initial_state.clone(), None, (0, 0))];
+    let mut costs = HashMap::from([(
+        (initial_state.boxes().clone(), initial_state.player_position),
+        (0, 0),
+    )]);
+    let mut open = BinaryHeap::new();
+    open.push(Reverse((heuristic(&initial_state)?, 0)));
+    while let Some(Reverse((_, node_index))) = open.pop() {
+        if start_time.elapsed() >= time_limit {
+            return None;
+        }
+        let (state, _, cost) = &nodes[node_index];
+        let (state, cost) = (state.clone(), *cost);
+        if costs[&(state.boxes().clone(), state.player_position)] < cost {
+            continue;
+        }
+        if state.is_solved(solver) {
+            return Some(vicinity_solution(map, &nodes, node_index));
+        }
+
+        let player_distances = state.player_distances(floors);
+        for box_index in state.boxes().iter() {
+            for push_direction in Direction::iter() {
+                let Some(player_index) = floors.neighbor(box_index, -push_direction) else {
+                    continue;
+                };
+                if player_distances[player_index] == i32::MAX {
                     continue;
                 }
+                let Some(new_box_index) = floors.neighbor(box_index, push_direction) else {
+                    continue;
+                };
+                if state.boxes().contains(new_box_index) {
+                    continue;
+                }
+                let mut new_state = state.clone();
+                new_state.move_box(box_index, new_box_index, floors);
+                new_state.player_position = floors.position(box_index);
+                if !is_in_vicinity(new_state.boxes()) {
+                    continue;
+                }
+                let Some(new_heuristic) = heuristic(&new_state) else {
+                    continue;
+                };
 
-                for (up, right, down, left) in Direction::iter().circular_tuple_windows() {
-                    let push_direction = up;
-                    let (up, right, down, left) =
-                        (up.into(), right.into(), down.into(), left.into());
-
-                    let player_position = box_position + &down;
-
-                    // Tunnel patterns:
-                    //  .      .      .
-                    // #$# or #$_ or _$#
-                    // #@#    #@#    #@#
-                    if self.map[player_position + &left].intersects(Tiles::Wall)
-                        && self.map[player_position + &right].intersects(Tiles::Wall)
-                        && (self.map[box_position + &left].intersects(Tiles::Wall)
-                            && self.map[box_position + &right].intersects(Tiles::Wall)
-                            || self.map[box_position + &right].intersects(Tiles::Wall)
-                                && self.map[box_position + &left].intersects(Tiles::Floor)
-                            || self.map[box_position + &right].intersects(Tiles::Floor)
-                                && self.map[box_position + &left].intersects(Tiles::Wall))
-                        && self.map[box_position].intersects(Tiles::Floor)
-                        && self.lower_bounds().contains_key(&(box_position + &up))
-                        && !self.map[box_position].intersects(Tiles::Goal)
-                    {
-                        tunnels.insert((player_position, push_direction));
-                    }
+                let moves = player_distances[player_index] as usize + 1;
+                let new_cost = match metric {
+                    Metric::Moves => (cost.0 + moves, cost.1 + 1),
+                    Metric::Pushes => (cost.0 + 1, cost.1 + moves),
+                };
+                let priority = (new_cost.0 + new_heuristic.0, new_cost.1 + new_heuristic.1);
+                if priority >= upper_bound {
+                    continue;
                 }
+                let key = (new_state.boxes().clone(), new_state.player_position);
+                if costs
+                    .get(&key)
+                    .is_some_and(|&old_cost| old_cost <= new_cost)
+                {
+                    continue;
+                }
+                costs.insert(key, new_cost);
+                nodes.push((new_state, Some((node_index, push_direction)), new_cost));
+                open.push(Reverse((priority, nodes.len() - 1)));
             }
         }
-        tunnels
     }
+    None
+}
 
-    fn construct_actions(&self, mut state: State, came_from: &HashMap<State, State>) -> Actions {
-        let mut actions = Actions::new();
-        while let Some(previous_state) = came_from.get(&state) {
-            // Find the positions where the box was moved from and to
-            let previous_box_position = *previous_state
-                .box_positions
-                .difference(&state.box_positions)
-                .next()
-                .unwrap();
-            let box_position = *state
-                .box_positions
-                .difference(&previous_state.box_positions)
-                .next()
-                .unwrap();
+/// Constructs the actions reaching the node of the vicinity search, walking
+/// the player along shortest paths between the pushes.
+fn vicinity_solution(map: &Map, nodes: &[VicinityNode], mut node_index: usize) -> Actions {
+    let mut pushes = Vec::new();
+    while let (state, Some((previous_index, push_direction)), _) = &nodes[node_index] {
+        // The player stands where the box was before the push
+        pushes.push((state.player_position, *push_direction));
+        node_index = *previous_index;
+    }
 
-            // Determine the direction of the push
-            let diff = box_position - previous_box_position;
-            let push_direction =
-                Direction::try_from(IVector2::new(diff.x.signum(), diff.y.signum())).unwrap();
-
-            // Find the path for the player to reach the box position before pushing it
-            let mut new_actions: Vec<_> = find_path(
-                previous_state.player_position,
-                previous_box_position - &push_direction.into(),
-                |position| {
-                    !self.map()[position].intersects(Tiles::Wall)
-                        && !previous_state.box_positions.contains(&position)
-                },
-            )
-            .unwrap()
-            .windows(2)
-            .map(|position| Direction::try_from(position[1] - position[0]).unwrap())
-            .map(Action::Move)
-            .collect();
-
-            new_actions.push(Action::Push(push_direction));
-
-            let mut new_box_position = previous_box_position + &push_direction.into();
-            while self.tunnels().contains(&(new_box_position, push_direction)) {
-                new_box_position += &push_direction.into();
-                new_actions.push(Action::Push(push_direction));
-            }
+    let mut level = Level::from_map(map.clone());
+    for (box_position, push_direction) in pushes.into_iter().rev() {
+        let player_position = box_position - &push_direction.into();
+        let walk = player_move_path(level.map(), player_position).unwrap();
+        level.do_actions(walk).unwrap();
+        level.do_action(push_direction).unwrap();
+    }
+    level.actions().clone()
+}
 
-            actions.splice(0..0, new_actions.iter().copied());
-            state = previous_state.clone();
+/// Replaces the player walks of the actions with shortest paths, keeping the
+/// pushes.
+///
+/// The player walks to the side of the box each push is made from, and the
+/// moves after the last push are dropped, so the actions reach the same box
+/// positions with the fewest moves for their pushes.
+pub fn minimize_moves(map: &Map, actions: &Actions) -> Result<Actions, VerifyError> {
+    map.replay(actions)?;
+
+    let mut level = Level::from_map(map.clone());
+    let mut player_position = map.player_position();
+    for action in actions.iter() {
+        if action.is_push() {
+            let walk = player_move_path(level.map(), player_position).unwrap();
+            level.do_actions(walk).unwrap();
+            level.do_action(action.direction()).unwrap();
         }
-        actions
+        player_position += &action.direction().into();
     }
+    Ok(level.actions().clone())
 }
@@ -1,58 +1,476 @@
-use std::{
-    collections::HashSet,
-    hash::{DefaultHasher, Hash, Hasher},
-};
-
-use crate::{
-    math::IVector2,
-    path_finding::{normalized_area, reachable_area},
-    solver::Solver,
-    Map, Tiles,
-};
+use std::collections::{hash_map::Entry, HashMap, VecDeque};
 
+use crate::{direction::Direction, math::IVector2, solver::Solver, Map, Tiles};
+
+/// A fixed-size set of floor indices.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct BitSet(Box<[u64]>);
+
+impl BitSet {
+    /// Creates an empty set able to hold indices in `0..len`.
+    pub fn new(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(64)].into_boxed_slice())
+    }
+
+    /// Returns `true` if the set contains the index.
+    pub fn contains(&self, index: usize) -> bool {
+        self.0[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    /// Adds an index to the set.
+    pub fn insert(&mut self, index: usize) {
+        self.0[index / 64] |= 1 << (index % 64);
+    }
+
+    /// Removes an index from the set.
+    pub fn remove(&mut self, index: usize) {
+        self.0[index / 64] &= !(1 << (index % 64));
+    }
+
+    /// Returns an iterator over the indices in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(i, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(i * 64 + bit)
+            })
+        })
+    }
+
+    /// Returns an iterator over the indices in `self` but not in `other`.
+    pub fn difference<'a>(&'a self, other: &'a BitSet) -> impl Iterator<Item = usize> + 'a {
+        self.iter().filter(|&index| !other.contains(index))
+    }
+}
+
+/// Dense indexing of the floor cells of a map.
+///
+/// Floor cells are numbered from top to bottom and left to right, so the
+/// smallest index of an area is the position returned by
+/// [`normalized_area`](crate::path_finding::normalized_area). Each cell also
+/// owns a pair of Zobrist keys used to hash states incrementally.
+#[derive(Clone, Debug)]
+pub struct Floors {
+    dimensions: IVector2,
+    indices: Vec<Option<usize>>,
+    positions: Vec<IVector2>,
+    neighbors: Vec<[Option<usize>; 4]>,
+    goals: BitSet,
+    box_keys: Vec<u64>,
+    player_keys: Vec<u64>,
+}
+
+impl Floors {
+    /// Creates a new `Floors` from the map.
+    pub fn new(map: &Map) -> Self {
+        let dimensions = map.dimensions();
+        let mut indices = vec![None; (dimensions.x * dimensions.y) as usize];
+        let mut positions = Vec::new();
+        for y in 0..dimensions.y {
+            for x in 0..dimensions.x {
+                let position = IVector2::new(x, y);
+                if map[position].intersects(Tiles::Floor | Tiles::Box | Tiles::Goal)
+                    && !map[position].intersects(Tiles::Wall)
+                {
+                    indices[(y * dimensions.x + x) as usize] = Some(positions.len());
+                    positions.push(position);
+                }
+            }
+        }
+
+        let mut instance = Self {
+            dimensions,
+            indices,
+            neighbors: Vec::with_capacity(positions.len()),
+            goals: BitSet::new(positions.len()),
+            box_keys: Vec::with_capacity(positions.len()),
+            player_keys: Vec::with_capacity(positions.len()),
+            positions,
+        };
+        for i in 0..instance.positions.len() {
+            let position = instance.positions[i];
+            let mut neighbors = [None; 4];
+            for direction in Direction::iter() {
+                neighbors[direction as usize] = instance.index(position + &direction.into());
+            }
+            instance.neighbors.push(neighbors);
+        }
+        for goal_position in map.goal_positions() {
+            let index = instance
+                .index(*goal_position)
+                .expect("goal is not on floor");
+            instance.goals.insert(index);
+        }
+
+        let mut seed = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..instance.positions.len() {
+            instance.box_keys.push(split_mix64(&mut seed));
+            instance.player_keys.push(split_mix64(&mut seed));
+        }
+        instance
+    }
+
+    /// Returns the number of floor cells.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Returns the index of the floor cell at the position, or `None` if the
+    /// position is not a floor cell.
+    pub fn index(&self, position: IVector2) -> Option<usize> {
+        if position.x < 0
+            || position.y < 0
+            || position.x >= self.dimensions.x
+            || position.y >= self.dimensions.y
+        {
+            return None;
+        }
+        self.indices[(position.y * self.dimensions.x + position.x) as usize]
+    }
+
+    /// Returns the position of the floor cell with the index.
+    pub fn position(&self, index: usize) -> IVector2 {
+        self.positions[index]
+    }
+
+    /// Returns the index of the neighboring floor cell in the direction.
+    pub fn neighbor(&self, index: usize, direction: Direction) -> Option<usize> {
+        self.neighbors[index][direction as usize]
+    }
+
+    /// Returns the set of goal indices.
+    pub fn goals(&self) -> &BitSet {
+        &self.goals
+    }
+}
+
+/// A search state.
+///
+/// Boxes are stored as a bitset indexed by [`Floors`], together with the
+/// Zobrist hash of the box set which is updated on every push.
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct State {
     pub player_position: IVector2,
-    pub box_positions: HashSet<IVector2>,
+    boxes: BitSet,
+    box_hash: u64,
 }
 
 impl State {
+    /// Creates a new `State`.
+    pub fn new(
+        player_position: IVector2,
+        box_positions: impl IntoIterator<Item = IVector2>,
+        floors: &Floors,
+    ) -> Self {
+        let mut boxes = BitSet::new(floors.len());
+        let mut box_hash = 0;
+        for box_position in box_positions {
+            let index = floors.index(box_position).expect("box is not on floor");
+            boxes.insert(index);
+            box_hash ^= floors.box_keys[index];
+        }
+        Self {
+            player_position,
+            boxes,
+            box_hash,
+        }
+    }
+
+    /// Creates a new `State` from the player and box positions of the map.
+    pub fn from_map(map: &Map, floors: &Floors) -> Self {
+        Self::new(
+            map.player_position(),
+            map.box_positions().iter().copied(),
+            floors,
+        )
+    }
+
+    /// Returns the set of box indices.
+    pub fn boxes(&self) -> &BitSet {
+        &self.boxes
+    }
+
+    /// Returns `true` if there is a box at the position.
+    pub fn has_box(&self, position: IVector2, floors: &Floors) -> bool {
+        floors
+            .index(position)
+            .is_some_and(|index| self.boxes.contains(index))
+    }
+
+    /// Returns an iterator over the box positions.
+    pub fn box_positions<'a>(&'a self, floors: &'a Floors) -> impl Iterator<Item = IVector2> + 'a {
+        self.boxes.iter().map(|index| floors.position(index))
+    }
+
+    /// Moves a box between two floor cells and updates the hash.
+    pub fn move_box(&mut self, from: usize, to: usize, floors: &Floors) {
+        debug_assert!(self.boxes.contains(from) && !self.boxes.contains(to));
+        self.boxes.remove(from);
+        self.boxes.insert(to);
+        self.box_hash ^= floors.box_keys[from] ^ floors.box_keys[to];
+    }
+
     /// Returns true if the state is solved.
     pub fn is_solved(&self, solver: &Solver) -> bool {
-        self.box_positions == *solver.map().goal_positions()
+        self.boxes == *solver.floors().goals()
     }
 
-    /// Returns the heuristic value of the state.
-    pub fn heuristic(&self, solver: &Solver) -> i32 {
-        self.box_positions
-            .iter()
-            .map(|box_position| solver.lower_bounds()[box_position])
+    /// Returns the heuristic value of the state, or `None` if some box can not
+    /// be pushed to any goal.
+    pub fn heuristic(&self, solver: &Solver) -> Option<i32> {
+        self.box_positions(solver.floors())
+            .map(|box_position| solver.lower_bounds().get(&box_position).copied())
             .sum()
     }
 
-    /// Normalizes the state.
-    pub fn normalize(&mut self, map: &Map) {
-        self.player_position = normalized_area(&reachable_area(self.player_position, |position| {
-            !(map[position].intersects(Tiles::Wall) || self.box_positions.contains(&position))
-        }))
-        .unwrap();
+    /// Returns the lower bound of the moves the player needs to walk before the
+    /// next push.
+    ///
+    /// Unless the state is solved, the player has to stand next to some box.
+    pub fn walk_lower_bound(&self, solver: &Solver) -> i32 {
+        if self.is_solved(solver) {
+            return 0;
+        }
+        self.box_positions(solver.floors())
+            .map(|box_position| self.player_position.manhattan_distance(box_position) - 1)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of moves the player needs to reach each floor cell,
+    /// or `i32::MAX` for unreachable cells.
+    pub fn player_distances(&self, floors: &Floors) -> Vec<i32> {
+        let mut distances = vec![i32::MAX; floors.len()];
+        let start = floors
+            .index(self.player_position)
+            .expect("player is not on floor");
+        distances[start] = 0;
+        let mut deque = VecDeque::from([start]);
+        while let Some(index) = deque.pop_front() {
+            for direction in Direction::iter() {
+                let Some(neighbor) = floors.neighbor(index, direction) else {
+                    continue;
+                };
+                if distances[neighbor] != i32::MAX || self.boxes.contains(neighbor) {
+                    continue;
+                }
+                distances[neighbor] = distances[index] + 1;
+                deque.push_back(neighbor);
+            }
+        }
+        distances
+    }
+
+    /// Returns the directions of the fewest pushes moving the box to the target
+    /// cell with the other boxes fixed, or `None` if it can not be moved there.
+    pub fn box_pushes(
+        &self,
+        box_index: usize,
+        target: usize,
+        floors: &Floors,
+    ) -> Option<Vec<Direction>> {
+        type Key = (usize, usize);
+        let start: Key = (box_index, self.normalized_player_index(floors));
+        let mut came_from: HashMap<Key, Option<(Key, Direction)>> = HashMap::from([(start, None)]);
+        let mut queue = VecDeque::from([(self.clone(), start)]);
+        while let Some((state, key)) = queue.pop_front() {
+            let (box_index, _) = key;
+            if box_index == target {
+                let mut directions = Vec::new();
+                let mut key = key;
+                while let Some((previous_key, direction)) = came_from[&key] {
+                    directions.push(direction);
+                    key = previous_key;
+                }
+                directions.reverse();
+                return Some(directions);
+            }
+
+            let player_distances = state.player_distances(floors);
+            for push_direction in Direction::iter() {
+                let Some(player_index) = floors.neighbor(box_index, -push_direction) else {
+                    continue;
+                };
+                if player_distances[player_index] == i32::MAX {
+                    continue;
+                }
+                let Some(new_box_index) = floors.neighbor(box_index, push_direction) else {
+                    continue;
+                };
+                if state.boxes.contains(new_box_index) {
+                    continue;
+                }
+                let mut new_state = state.clone();
+                new_state.move_box(box_index, new_box_index, floors);
+                new_state.player_position = floors.position(box_index);
+                let new_key = (new_box_index, new_state.normalized_player_index(floors));
+                if let Entry::Vacant(entry) = came_from.entry(new_key) {
+                    entry.insert(Some((key, push_direction)));
+                    queue.push_back((new_state, new_key));
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the index of the top-left floor cell reachable by the player.
+    pub fn normalized_player_index(&self, floors: &Floors) -> usize {
+        let start = floors
+            .index(self.player_position)
+            .expect("player is not on floor");
+        let mut visited = BitSet::new(floors.len());
+        visited.insert(start);
+        let mut stack = vec![start];
+        let mut min_index = start;
+        while let Some(index) = stack.pop() {
+            min_index = min_index.min(index);
+            for direction in Direction::iter() {
+                let Some(neighbor) = floors.neighbor(index, direction) else {
+                    continue;
+                };
+                if visited.contains(neighbor) || self.boxes.contains(neighbor) {
+                    continue;
+                }
+                visited.insert(neighbor);
+                stack.push(neighbor);
+            }
+        }
+        min_index
+    }
+
+    /// Returns the hash of the state, including the exact player position.
+    pub fn hash(&self, floors: &Floors) -> u64 {
+        let player_index = floors
+            .index(self.player_position)
+            .expect("player is not on floor");
+        self.box_hash ^ floors.player_keys[player_index]
     }
 
     /// Returns the hash of the normalized state.
-    pub fn normalized_hash(&self, map: &Map) -> u64 {
-        let mut normalized_state = self.clone();
-        normalized_state.normalize(map);
-        let mut hasher = DefaultHasher::new();
-        normalized_state.hash(&mut hasher);
-        hasher.finish()
+    pub fn normalized_hash(&self, floors: &Floors) -> u64 {
+        self.box_hash ^ floors.player_keys[self.normalized_player_index(floors)]
     }
 }
 
-impl Hash for State {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.player_position.hash(state);
-        for box_position in &self.box_positions {
-            box_position.hash(state);
+/// The moves and turns of arriving at a cell in a direction, and the direction
+/// the player was facing before the last move.
+type WalkEntry = Option<(i32, i32, Option<Direction>)>;
+
+/// The shortest walks of the player in a state.
+///
+/// Among walks of equal length, walks with fewer turns are preferred. Turns
+/// are counted as changes of the player's direction, starting from the
+/// direction the player is facing.
+pub struct PlayerWalks {
+    start: usize,
+    entries: Vec<[WalkEntry; 4]>,
+}
+
+impl PlayerWalks {
+    /// Calculates the walks of the player in the state.
+    pub fn new(state: &State, floors: &Floors, facing: Option<Direction>) -> Self {
+        let start = floors
+            .index(state.player_position)
+            .expect("player is not on floor");
+        let mut entries = vec![[None; 4]; floors.len()];
+        let mut deque = VecDeque::new();
+        let relax = |index: usize,
+                     moves: i32,
+                     turns: i32,
+                     facing: Option<Direction>,
+                     entries: &mut Vec<[WalkEntry; 4]>,
+                     deque: &mut VecDeque<(usize, Direction)>| {
+            for direction in Direction::iter() {
+                let Some(neighbor) = floors.neighbor(index, direction) else {
+                    continue;
+                };
+                if state.boxes.contains(neighbor) {
+                    continue;
+                }
+                let new_entry = (
+                    moves + 1,
+                    turns + (facing != Some(direction)) as i32,
+                    facing,
+                );
+                match &mut entries[neighbor][direction as usize] {
+                    Some(entry) => {
+                        if entry.0 == new_entry.0 && entry.1 > new_entry.1 {
+                            *entry = new_entry;
+                        }
+                    }
+                    entry @ None => {
+                        *entry = Some(new_entry);
+                        deque.push_back((neighbor, direction));
+                    }
+                }
+            }
+        };
+        relax(start, 0, 0, facing, &mut entries, &mut deque);
+        while let Some((index, direction)) = deque.pop_front() {
+            let (moves, turns, _) = entries[index][direction as usize].unwrap();
+            relax(
+                index,
+                moves,
+                turns,
+                Some(direction),
+                &mut entries,
+                &mut deque,
+            );
         }
+        Self { start, entries }
     }
+
+    /// Returns the direction the player arrives at the cell with before
+    /// pushing in the direction, or `None` if the player is already there.
+    fn arrival(&self, index: usize, push_direction: Direction) -> Option<Option<Direction>> {
+        if index == self.start {
+            return Some(None);
+        }
+        Direction::iter()
+            .filter_map(|direction| {
+                self.entries[index][direction as usize].map(|(moves, turns, _)| {
+                    let turns = turns + (direction != push_direction) as i32;
+                    ((moves, turns), direction)
+                })
+            })
+            .min_by_key(|(cost, _)| *cost)
+            .map(|(_, direction)| Some(direction))
+    }
+
+    /// Returns the directions of the walk to the cell before pushing in the
+    /// direction, or `None` if it is unreachable.
+    pub fn path(
+        &self,
+        index: usize,
+        push_direction: Direction,
+        floors: &Floors,
+    ) -> Option<Vec<Direction>> {
+        let mut path = Vec::new();
+        let mut index = index;
+        let mut facing = self.arrival(index, push_direction)?;
+        while let Some(direction) = facing {
+            path.push(direction);
+            facing = self.entries[index][direction as usize].unwrap().2;
+            index = floors.neighbor(index, -direction).unwrap();
+            if index == self.start {
+                break;
+            }
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+/// Generates the next value of a SplitMix64 sequence.
+fn split_mix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
 }
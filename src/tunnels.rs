@@ -0,0 +1,186 @@
+//! Tunnels of a map.
+
+use std::collections::HashMap;
+
+use crate::{
+    direction::Direction, map::Map, math::IVector2, push_distances::PushDistances, state::Floors,
+};
+
+/// A straight corridor of floor cells, each with non-floor cells on both
+/// sides.
+///
+/// Since the corridor is one cell wide, the player can not pass a box in it,
+/// so a box in a tunnel can only be pushed along it.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Tunnel {
+    /// The cells in the order of `direction`.
+    cells: Vec<IVector2>,
+    direction: Direction,
+    push_directions: Vec<Direction>,
+    /// Whether both ends of the tunnel lead to floor cells.
+    is_passage: bool,
+    /// Whether the player can get around a box in the tunnel through other
+    /// cells.
+    has_detour: bool,
+}
+
+impl Tunnel {
+    /// Returns the positions of the tunnel, in the order of
+    /// [`direction`](Self::direction).
+    pub fn cells(&self) -> &[IVector2] {
+        &self.cells
+    }
+
+    /// Returns the direction along the tunnel, either [`Direction::Right`] or
+    /// [`Direction::Up`].
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Returns the directions a box can be pushed into the tunnel from outside
+    /// and still be pushed to a goal.
+    pub fn push_directions(&self) -> &[Direction] {
+        &self.push_directions
+    }
+
+    /// Returns `true` if only the player can pass through the tunnel.
+    ///
+    /// The player can walk through a player tunnel between the areas at both
+    /// of its ends, but no box can be pushed into it and still be pushed to a
+    /// goal. Since the player can not pass a box in the tunnel, such a box
+    /// would only block the passage.
+    pub fn is_player_tunnel(&self) -> bool {
+        self.is_passage && self.push_directions.is_empty()
+    }
+
+    /// Returns `true` if boxes can only be pushed into the tunnel in one
+    /// direction.
+    pub fn is_one_way(&self) -> bool {
+        self.push_directions.len() == 1
+    }
+
+    /// Returns `true` if the player can get around a box in the tunnel through
+    /// other cells, and push it back.
+    pub(crate) fn has_detour(&self) -> bool {
+        self.has_detour
+    }
+}
+
+/// The tunnels of a map.
+///
+/// Since tunnels are only determined by the map terrain, they can be
+/// pre-calculated.
+#[derive(Clone, Debug)]
+pub struct Tunnels {
+    tunnels: Vec<Tunnel>,
+    /// The index of the tunnel containing each position.
+    indices: HashMap<IVector2, usize>,
+}
+
+impl Tunnels {
+    /// Finds the tunnels of the map.
+    pub fn new(map: &Map) -> Self {
+        Self::with_push_distances(map, &PushDistances::new(map))
+    }
+
+    /// Finds the tunnels of the map, with the push distances of the map.
+    pub(crate) fn with_push_distances(map: &Map, push_distances: &PushDistances) -> Self {
+        let floors = Floors::new(map);
+        let mut tunnels = Vec::new();
+        for (direction, sides) in [
+            (Direction::Right, [Direction::Up, Direction::Down]),
+            (Direction::Up, [Direction::Left, Direction::Right]),
+        ] {
+            let is_tunnel_cell = |index: usize| {
+                sides
+                    .iter()
+                    .all(|&side| floors.neighbor(index, side).is_none())
+            };
+            for start in (0..floors.len()).filter(|&index| is_tunnel_cell(index)) {
+                if floors
+                    .neighbor(start, -direction)
+                    .is_some_and(is_tunnel_cell)
+                {
+                    continue;
+                }
+                let mut cells = vec![floors.position(start)];
+                let mut end = start;
+                while let Some(next_index) = floors
+                    .neighbor(end, direction)
+                    .filter(|&index| is_tunnel_cell(index))
+                {
+                    cells.push(floors.position(next_index));
+                    end = next_index;
+                }
+                // Cells with no neighbors at all are not tunnels
+                if cells.len() == 1
+                    && floors.neighbor(start, direction).is_none()
+                    && floors.neighbor(start, -direction).is_none()
+                {
+                    continue;
+                }
+
+                // A box is pushed into the tunnel onto the cell at the end it
+                // enters from, with the player two cells behind that end
+                let push_directions = [(direction, start), (-direction, end)]
+                    .into_iter()
+                    .filter(|&(push_direction, entrance)| {
+                        let can_enter = floors
+                            .neighbor(entrance, -push_direction)
+                            .and_then(|index| floors.neighbor(index, -push_direction))
+                            .is_some();
+                        can_enter
+                            && map.goal_positions().iter().any(|&goal_position| {
+                                push_distances
+                                    .get_from_side(
+                                        floors.position(entrance),
+                                        -push_direction,
+                                        goal_position,
+                                    )
+                                    .is_some()
+                            })
+                    })
+                    .map(|(push_direction, _)| push_direction)
+                    .collect();
+                let is_passage = floors.neighbor(start, -direction).is_some()
+                    && floors.neighbor(end, direction).is_some();
+                let has_detour = push_distances.connects_sides(cells[0], -direction, direction);
+                tunnels.push(Tunnel {
+                    cells,
+                    direction,
+                    push_directions,
+                    is_passage,
+                    has_detour,
+                });
+            }
+        }
+
+        let indices = tunnels
+            .iter()
+            .enumerate()
+            .flat_map(|(i, tunnel)| tunnel.cells.iter().map(move |&cell| (cell, i)))
+            .collect();
+        Self { tunnels, indices }
+    }
+
+    /// Returns an iterator over the tunnels.
+    pub fn iter(&self) -> impl Iterator<Item = &Tunnel> + '_ {
+        self.tunnels.iter()
+    }
+
+    /// Returns the number of tunnels.
+    pub fn len(&self) -> usize {
+        self.tunnels.len()
+    }
+
+    /// Returns `true` if there are no tunnels.
+    pub fn is_empty(&self) -> bool {
+        self.tunnels.is_empty()
+    }
+
+    /// Returns the tunnel containing the position, or `None` if it is not in
+    /// a tunnel.
+    pub fn get(&self, position: IVector2) -> Option<&Tunnel> {
+        self.indices.get(&position).map(|&i| &self.tunnels[i])
+    }
+}
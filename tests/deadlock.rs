@@ -1,4 +1,10 @@
-use sokoban_core::deadlock;
+use std::{collections::HashSet, str::FromStr};
+
+use indoc::indoc;
+use sokoban_core::{
+    deadlock::{self, DeadlockKind},
+    IVector2, Map,
+};
 
 mod utils;
 use utils::*;
@@ -11,3 +17,214 @@ fn calculate_static_deadlocks() {
     let map = load_level_from_file("assets/BoxWorld_100.xsb", 9).into();
     assert_eq!(deadlock::calculate_static_deadlocks(&map).len(), 17);
 }
+
+#[test]
+fn is_bipartite_deadlock() {
+    // Both boxes can only reach the goal in the bottom row
+    let map = Map::from_str(indoc! {"
+        #######
+        #.  @ #
+        ##### #
+        #.$ $ #
+        #######
+    "})
+    .unwrap();
+    assert!(deadlock::is_bipartite_deadlock(&map, map.box_positions()));
+
+    let mut box_positions = map.box_positions().clone();
+    box_positions.remove(&IVector2::new(4, 1));
+    box_positions.insert(IVector2::new(3, 3));
+    assert!(!deadlock::is_bipartite_deadlock(&map, &box_positions));
+
+    for id in 1..=10 {
+        let map: Map = load_level_from_file("assets/Microban_155.xsb", id).into();
+        assert!(!deadlock::is_bipartite_deadlock(&map, map.box_positions()));
+    }
+}
+
+#[test]
+fn calculate_dead_squares() {
+    for id in 1..=20 {
+        let map: Map = load_level_from_file("assets/Microban_155.xsb", id).into();
+        let dead_squares = deadlock::calculate_dead_squares(&map);
+        assert!(deadlock::calculate_static_deadlocks(&map).is_subset(&dead_squares));
+        assert!(dead_squares.is_disjoint(map.goal_positions()));
+    }
+
+    // The box can be pushed along the wall to the goal, but not away from it
+    let map = Map::from_str(indoc! {"
+        #######
+        #.    #
+        #  $ @#
+        ####  #
+           ####
+    "})
+    .unwrap();
+    assert_eq!(
+        deadlock::calculate_dead_squares(&map),
+        HashSet::from([
+            IVector2::new(5, 3),
+            IVector2::new(4, 1),
+            IVector2::new(5, 1),
+            IVector2::new(5, 2),
+            IVector2::new(1, 2),
+        ])
+    );
+}
+
+#[test]
+fn is_closed_diagonal_deadlock() {
+    // The boxes can only be pushed into the corner between them
+    let map = Map::from_str(indoc! {"
+        #######
+        #  @  #
+        # #$  #
+        # $ # #
+        #.##. #
+        #######
+    "})
+    .unwrap();
+    for &box_position in map.box_positions() {
+        assert!(!deadlock::is_freeze_deadlock(
+            &map,
+            box_position,
+            map.box_positions(),
+            &mut HashSet::new()
+        ));
+        assert!(deadlock::is_closed_diagonal_deadlock(
+            &map,
+            box_position,
+            map.box_positions(),
+            map.player_position()
+        ));
+    }
+
+    // The player can push the boxes out of the corner
+    let map = Map::from_str(indoc! {"
+        #######
+        #     #
+        # #$  #
+        # $@# #
+        #.##. #
+        #######
+    "})
+    .unwrap();
+    for &box_position in map.box_positions() {
+        assert!(!deadlock::is_closed_diagonal_deadlock(
+            &map,
+            box_position,
+            map.box_positions(),
+            map.player_position()
+        ));
+    }
+
+    // The boxes on goals are never moved
+    let map = Map::from_str(indoc! {"
+        #######
+        #  @  #
+        # #*  #
+        # * # #
+        # ##  #
+        #######
+    "})
+    .unwrap();
+    assert_eq!(
+        &deadlock::calculate_useless_boxes(&map),
+        map.box_positions()
+    );
+}
+
+#[test]
+fn is_frozen_corral_deadlock() {
+    // The boxes on goals close the right room without goals
+    let map = Map::from_str(indoc! {"
+        ##########
+        #   ##   #
+        #.@ ** $ #
+        #   ##   #
+        ##########
+    "})
+    .unwrap();
+    assert!(deadlock::is_frozen_corral_deadlock(
+        &map,
+        map.box_positions(),
+        map.player_position()
+    ));
+
+    let map = Map::from_str(indoc! {"
+        ##########
+        #   ##   #
+        # @ ** $.#
+        #   ##   #
+        ##########
+    "})
+    .unwrap();
+    assert!(!deadlock::is_frozen_corral_deadlock(
+        &map,
+        map.box_positions(),
+        map.player_position()
+    ));
+
+    // Frozen boxes not on goals are freeze deadlocks, not corral deadlocks
+    let map = Map::from_str(indoc! {"
+        #######
+        #     #
+        # $$  #
+        # $$ @#
+        #.... #
+        #######
+    "})
+    .unwrap();
+    assert!(deadlock::is_freeze_deadlock(
+        &map,
+        IVector2::new(2, 2),
+        map.box_positions(),
+        &mut HashSet::new()
+    ));
+    assert!(!deadlock::is_frozen_corral_deadlock(
+        &map,
+        map.box_positions(),
+        map.player_position()
+    ));
+
+    for id in 1..=10 {
+        let map: Map = load_level_from_file("assets/Microban_155.xsb", id).into();
+        assert!(!deadlock::is_frozen_corral_deadlock(
+            &map,
+            map.box_positions(),
+            map.player_position()
+        ));
+    }
+}
+
+#[test]
+fn find_deadlock() {
+    let map = Map::from_str(indoc! {"
+        ##########
+        #   ##   #
+        #.@ ** $ #
+        #   ##   #
+        ##########
+    "})
+    .unwrap();
+    let deadlock = deadlock::find_deadlock(&map).unwrap();
+    assert_eq!(deadlock.kind, DeadlockKind::Corral);
+    assert_eq!(deadlock.box_positions, HashSet::from([IVector2::new(7, 2)]));
+
+    let map = Map::from_str(indoc! {"
+        ########
+        #.   @ #
+        ###### #
+        #.$  $ #
+        ########
+    "})
+    .unwrap();
+    let deadlock = deadlock::find_deadlock(&map).unwrap();
+    assert_eq!(deadlock.kind, DeadlockKind::Bipartite);
+    assert_eq!(&deadlock.box_positions, map.box_positions());
+
+    for id in 1..=10 {
+        let map: Map = load_level_from_file("assets/Microban_155.xsb", id).into();
+        assert_eq!(deadlock::find_deadlock(&map), None);
+    }
+}
@@ -0,0 +1,106 @@
+use std::{collections::HashSet, str::FromStr};
+
+use indoc::indoc;
+use sokoban_core::{
+    deadlock::is_freeze_deadlock, deadlock_patterns::DeadlockPatterns, DecodeDeadlockPatternsError,
+    IVector2, Map,
+};
+
+#[test]
+fn builtin_patterns() {
+    let patterns = DeadlockPatterns::builtin();
+    assert!(!patterns.is_empty());
+    assert_eq!(*patterns, DeadlockPatterns::generate());
+    assert_eq!(
+        DeadlockPatterns::from_bytes(&patterns.to_bytes()).as_ref(),
+        Ok(patterns)
+    );
+
+    assert_eq!(
+        DeadlockPatterns::from_bytes(b"SKDP"),
+        Err(DecodeDeadlockPatternsError::InvalidHeader)
+    );
+    let mut bytes = patterns.to_bytes();
+    bytes.pop();
+    assert_eq!(
+        DeadlockPatterns::from_bytes(&bytes),
+        Err(DecodeDeadlockPatternsError::Truncated)
+    );
+}
+
+#[test]
+fn is_deadlock() {
+    let patterns = DeadlockPatterns::builtin();
+    let map = Map::from_str(indoc! {"
+        #######
+        #     #
+        # $$  #
+        # $$ @#
+        #.... #
+        #######
+    "})
+    .unwrap();
+    let has_box = |position| map.box_positions().contains(&position);
+    assert!(patterns.is_deadlock(&map, IVector2::new(2, 3), has_box, map.player_position()));
+    assert!(patterns.is_deadlock(&map, IVector2::new(3, 2), has_box, map.player_position()));
+
+    // Frozen boxes on goals are not deadlocks
+    let map = Map::from_str(indoc! {"
+        #######
+        #     #
+        # **  #
+        # ** @#
+        #     #
+        #######
+    "})
+    .unwrap();
+    let has_box = |position| map.box_positions().contains(&position);
+    assert!(!patterns.is_deadlock(&map, IVector2::new(2, 3), has_box, map.player_position()));
+
+    // Boxes which can be pushed apart are not deadlocks
+    let map = Map::from_str(indoc! {"
+        #######
+        #     #
+        # $ $ #
+        #  $ @#
+        #...  #
+        #######
+    "})
+    .unwrap();
+    let has_box = |position| map.box_positions().contains(&position);
+    assert!(!patterns.is_deadlock(&map, IVector2::new(3, 2), has_box, map.player_position()));
+
+    // A box in a corner is frozen by the walls
+    let map = Map::from_str(indoc! {"
+        #####
+        #$ .#
+        # @ #
+        #####
+    "})
+    .unwrap();
+    let has_box = |position| map.box_positions().contains(&position);
+    assert!(patterns.is_deadlock(&map, IVector2::new(1, 2), has_box, map.player_position()));
+
+    // The box can only be pushed into the pocket below it, which the freeze
+    // check misses since the box is not frozen yet
+    let map = Map::from_str(indoc! {"
+        ########
+        #      #
+        # #$   #
+        # # #  #
+        #  #   #
+        #.    @#
+        ########
+    "})
+    .unwrap();
+    let box_positions: HashSet<_> = map.box_positions().iter().copied().collect();
+    let box_position = IVector2::new(3, 4);
+    let has_box = |position| box_positions.contains(&position);
+    assert!(patterns.is_deadlock(&map, box_position, has_box, map.player_position()));
+    assert!(!is_freeze_deadlock(
+        &map,
+        box_position,
+        &box_positions,
+        &mut HashSet::new()
+    ));
+}
@@ -1,7 +1,11 @@
-use std::{fs, str::FromStr};
+use std::{fs, str::FromStr, time::Duration};
 
 use indoc::indoc;
-use sokoban_core::{Level, ParseLevelError, ParseMapError};
+use sokoban_core::{
+    deadlock::DeadlockKind,
+    solver::{Solver, Strategy, Terminator},
+    Direction, IVector2, Level, ParseLevelError, ParseMapError, SearchError, TerminationReason,
+};
 
 mod utils;
 use utils::*;
@@ -179,3 +183,102 @@ const MICROBAN_3_RLE: &str = "--4#|3#--4#|#5-$-#|#-#--#$-#|#-.-.#@-#|9#";
 
 // Microban II #132
 const MICROBAN2_132_RLE: &str = "18-5#|12-5#-#3-#|12-#3-3#-#-#|6-5#-#-#7-#|5#-#3-#-#3-4#-##|#3-3#-#-#-3#-#--#-#|#-#4-@--#3-#-#--#-3#|#3-4#$6#-4#3-#|3#-#--#-.6-#4-#-#|--#-#--#--##--#4-#3-#|-##-5#--##4-#-5#|-#9-##--3#-#|-#-#-3#-#--5#--#-5#|-#3-#-#4-#-#4-#-#3-#|-5#-#--5#--#-3#-#-#|7-#-3#--##9-#|3-5#-#4-##--5#-##|3-#3-#4-#--##--#--#-#|3-#-#4-#8-#--#-3#|3-#3-4#-6#-4#3-#|3-3#-#--#-#3-#7-#-#|5-#-#--#-3#-#-#-3#3-#|4-##-4#3-#-#3-#-5#|4-#7-#-#-5#|4-#-#-3#3-#|4-#3-#-5#|4-5#";
+
+#[test]
+fn deadlock_detection() {
+    let mut level = Level::from_str(indoc! {"
+        ######
+        #    #
+        #@$  #
+        #   .#
+        ######
+    "})
+    .unwrap();
+    level.set_deadlock_detection(true);
+    level
+        .do_actions([Direction::Right, Direction::Right, Direction::Down])
+        .unwrap();
+    assert_eq!(level.deadlock(), None);
+
+    // Push the box into the corner
+    level.do_actions([Direction::Right, Direction::Up]).unwrap();
+    let deadlock = level.deadlock().unwrap();
+    assert_eq!(deadlock.kind, DeadlockKind::Static);
+    assert_eq!(&deadlock.box_positions, level.map().box_positions());
+
+    level.undo_action().unwrap();
+    assert_eq!(level.deadlock(), None);
+    level.redo_action().unwrap();
+    assert_eq!(level.deadlock().unwrap().kind, DeadlockKind::Static);
+    level.set_deadlock_detection(false);
+    assert_eq!(level.deadlock(), None);
+
+    // Push the boxes next to each other along the wall
+    let mut level = Level::from_str(indoc! {"
+        ########
+        #.    .#
+        #  $$  #
+        #   @  #
+        ########
+    "})
+    .unwrap();
+    level.set_deadlock_detection(true);
+    level
+        .do_actions([Direction::Up, Direction::Down, Direction::Left])
+        .unwrap();
+    assert_eq!(level.deadlock(), None);
+    level.do_action(Direction::Up).unwrap();
+    let deadlock = level.deadlock().unwrap();
+    assert_eq!(deadlock.kind, DeadlockKind::Freeze);
+    assert_eq!(&deadlock.box_positions, level.map().box_positions());
+
+    // Levels at the same position are equal whether deadlocks are detected
+    let mut other_level = level.clone();
+    other_level.set_deadlock_detection(false);
+    assert_eq!(other_level, level);
+}
+
+#[test]
+fn hint() {
+    // Follow the hints until the level is solved
+    let mut level = load_level_from_file("assets/Microban_155.xsb", 1);
+    let solution = Solver::new(level.map().clone(), Strategy::OptimalPush)
+        .a_star_search()
+        .unwrap();
+    while let Some(hint) = level
+        .hint(Strategy::OptimalPush, Terminator::new_duration_secs(10))
+        .unwrap()
+    {
+        level
+            .do_actions(hint.walk.iter().map(|action| action.direction()))
+            .unwrap();
+        assert_eq!(
+            level.map().player_position() + IVector2::from(hint.direction),
+            hint.box_position
+        );
+        level.do_action(hint.direction).unwrap();
+        assert!(level.actions().last().unwrap().is_push());
+    }
+    assert!(level.is_solved());
+    assert_eq!(level.actions().pushes(), solution.pushes());
+
+    let level = Level::from_str(indoc! {"
+        ######
+        #   $#
+        #  @ #
+        #   .#
+        ######
+    "})
+    .unwrap();
+    assert_eq!(
+        level.hint(Strategy::Fast, Terminator::None),
+        Err(SearchError::NoSolution)
+    );
+
+    // A search running out of time is not mistaken for a deadlock
+    let level = load_level_from_file("assets/SokHard_163.xsb", 2);
+    assert_eq!(
+        level.hint(Strategy::OptimalPush, Terminator::Timeout(Duration::ZERO)),
+        Err(SearchError::Terminated(TerminationReason::Timeout))
+    );
+}
@@ -2,7 +2,11 @@ use std::str::FromStr;
 
 use indoc::indoc;
 use sokoban_core::IVector2;
-use sokoban_core::{tiles::Tiles, Actions, Map, ParseMapError};
+use sokoban_core::{
+    solver::{Solver, Strategy},
+    tiles::Tiles,
+    ActionError, Actions, Map, ParseMapError, VerifyError,
+};
 
 mod utils;
 use utils::*;
@@ -335,3 +339,48 @@ const WORLDCUP2014: &str = r#"
     ---#--#-###########----
     -------#--######-------
 "#;
+
+#[test]
+fn verify_solution() {
+    let map = Map::from_str(indoc! {"
+        #######
+        #@ $ .#
+        #######
+    "})
+    .unwrap();
+    let solution = Actions::from_str("rRR").unwrap();
+    let report = map.verify_solution(&solution).unwrap();
+    assert_eq!(report.moves, 3);
+    assert_eq!(report.pushes, 2);
+    assert_eq!(report.secondary_values, solution.secondary_values());
+
+    assert_eq!(
+        map.verify_solution(&Actions::from_str("lrRR").unwrap()),
+        Err(VerifyError::IllegalAction {
+            index: 0,
+            error: ActionError::MoveBlocked
+        })
+    );
+    assert_eq!(
+        map.verify_solution(&Actions::from_str("rRrR").unwrap()),
+        Err(VerifyError::MislabeledAction { index: 2 })
+    );
+    assert_eq!(
+        map.verify_solution(&Actions::from_str("RRR").unwrap()),
+        Err(VerifyError::MislabeledAction { index: 0 })
+    );
+    assert_eq!(
+        map.verify_solution(&Actions::from_str("rR").unwrap()),
+        Err(VerifyError::Unsolved)
+    );
+
+    for id in 1..=5 {
+        let level = load_level_from_file("assets/Microban_155.xsb", id);
+        let solution = Solver::new(level.map().clone(), Strategy::OptimalMove)
+            .a_star_search()
+            .unwrap();
+        let report = level.map().verify_solution(&solution).unwrap();
+        assert_eq!(report.moves, solution.moves());
+        assert_eq!(report.pushes, solution.pushes());
+    }
+}
@@ -0,0 +1,119 @@
+use std::str::FromStr;
+
+use indoc::indoc;
+use sokoban_core::{packing_order::PackingOrder, solver::*, IVector2, Map};
+
+mod utils;
+use utils::*;
+
+#[test]
+fn packing_order() {
+    let map = Map::from_str(indoc! {"
+        #########
+        #  $$ ..#
+        #@   ####
+        #########
+    "})
+    .unwrap();
+    let packing_order = PackingOrder::new(&map).unwrap();
+    assert_eq!(packing_order.entrance(), IVector2::new(5, 2));
+    assert_eq!(packing_order.room().count(), 2);
+    assert_eq!(
+        packing_order.order().collect::<Vec<_>>(),
+        [IVector2::new(7, 2), IVector2::new(6, 2)]
+    );
+
+    // The goal in the corner of the room must be filled first
+    let map = Map::from_str(indoc! {"
+        ########
+        #@ $ $ #
+        ###### #
+        #.  .  #
+        ########
+    "})
+    .unwrap();
+    let packing_order = PackingOrder::new(&map).unwrap();
+    assert_eq!(
+        packing_order.order().collect::<Vec<_>>(),
+        [IVector2::new(1, 1), IVector2::new(4, 1)]
+    );
+
+    // There is no entrance separating the goals from the boxes
+    let map = Map::from_str(indoc! {"
+        #######
+        #.    #
+        #  $  #
+        #@   .#
+        #   $ #
+        #######
+    "})
+    .unwrap();
+    assert!(PackingOrder::new(&map).is_none());
+}
+
+#[test]
+fn solve_with_packing_order() {
+    // The goals must be filled from the far end of the room
+    let map = Map::from_str(indoc! {"
+        ##########
+        #  @     #
+        # $ $ $  #
+        #  $  $  #
+        ####### ##
+        #        #
+        #.....   #
+        ##########
+    "})
+    .unwrap();
+    let config = SolverConfig::new().with_packing_order(true);
+    let solver = Solver::with_config(map.clone(), Strategy::Fast, config);
+    assert!(solver.config().follows_packing_order());
+    let solution = solver.a_star_search().unwrap();
+    assert!(map.verify_solution(&solution).is_ok());
+    let nodes_expanded = solver.stats().nodes_expanded;
+
+    let solver = Solver::new(map.clone(), Strategy::Fast);
+    solver.a_star_search().unwrap();
+    assert!(nodes_expanded < solver.stats().nodes_expanded);
+
+    let level = load_level_from_file("assets/XSokoban_90.xsb", 1);
+    let solver = Solver::with_config(level.map().clone(), Strategy::Fast, config);
+    let packing_order = solver.packing_order().unwrap();
+    assert_eq!(
+        packing_order.order().count(),
+        level.map().goal_positions().len()
+    );
+    let solution = solver.a_star_search().unwrap();
+    assert!(level.map().verify_solution(&solution).is_ok());
+}
+
+#[test]
+fn solve_with_goal_macros() {
+    // Boxes entering the goal room must turn to reach their goals
+    let map = Map::from_str(indoc! {"
+        #########
+        #  @    #
+        # $ $   #
+        ###### ##
+        #       #
+        #..     #
+        #########
+    "})
+    .unwrap();
+    let config = SolverConfig::new().with_goal_macros(true);
+    let solver = Solver::with_config(map.clone(), Strategy::Fast, config);
+    assert!(solver.config().goal_macros());
+    let solution = solver.a_star_search().unwrap();
+    assert!(map.verify_solution(&solution).is_ok());
+    let nodes_expanded = solver.stats().nodes_expanded;
+
+    let solver = Solver::new(map.clone(), Strategy::Fast);
+    solver.a_star_search().unwrap();
+    assert!(nodes_expanded < solver.stats().nodes_expanded);
+
+    let level = load_level_from_file("assets/XSokoban_90.xsb", 2);
+    let solver = Solver::with_config(level.map().clone(), Strategy::Fast, config)
+        .with_terminator(Terminator::new_iterations(10_000));
+    let solution = solver.a_star_search().unwrap();
+    assert!(level.map().verify_solution(&solution).is_ok());
+}
@@ -0,0 +1,67 @@
+use std::str::FromStr;
+
+use indoc::indoc;
+use sokoban_core::{push_distances::PushDistances, Direction, IVector2, Map};
+
+#[test]
+fn push_distances() {
+    let map = Map::from_str(indoc! {"
+        #######
+        #. $ @#
+        #######
+    "})
+    .unwrap();
+    let push_distances = PushDistances::new(&map);
+    let goal_position = IVector2::new(1, 1);
+    let box_position = IVector2::new(3, 1);
+    assert_eq!(push_distances.get(box_position, goal_position), Some(2));
+    assert_eq!(
+        push_distances.get_from_side(box_position, Direction::Right, goal_position),
+        Some(2)
+    );
+    assert_eq!(
+        push_distances.get_from_side(box_position, Direction::Left, goal_position),
+        None
+    );
+    assert_eq!(push_distances.get(IVector2::new(5, 1), goal_position), None);
+    assert_eq!(push_distances.get_nearest(goal_position), Some(0));
+    assert_eq!(push_distances.get_nearest(IVector2::new(0, 1)), None);
+
+    let map = Map::from_str(indoc! {"
+        #######
+        #.    #
+        #  $  #
+        #    .#
+        #@  $ #
+        #######
+    "})
+    .unwrap();
+    let push_distances = PushDistances::new(&map);
+    let box_position = IVector2::new(3, 3);
+    assert_eq!(
+        push_distances.get(box_position, IVector2::new(1, 4)),
+        Some(3)
+    );
+    assert_eq!(
+        push_distances.get(box_position, IVector2::new(5, 2)),
+        Some(3)
+    );
+    assert_eq!(push_distances.get_nearest(box_position), Some(3));
+    assert_eq!(push_distances.get_nearest(IVector2::new(5, 1)), None);
+
+    // The player below the box can walk around it to push it down
+    let map = Map::from_str(indoc! {"
+        #######
+        #     #
+        # # # #
+        #. $*@#
+        #   ###
+        #####
+    "})
+    .unwrap();
+    let push_distances = PushDistances::new(&map);
+    assert_eq!(
+        push_distances.get_from_side(IVector2::new(3, 3), Direction::Down, IVector2::new(4, 2)),
+        Some(2)
+    );
+}
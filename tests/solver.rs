@@ -1,5 +1,21 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use indoc::indoc;
 use sokoban_core::IVector2;
-use sokoban_core::{solver::*, Level, SearchError};
+use sokoban_core::{
+    direction::Direction,
+    solver::{self, *},
+    Action, Actions, Level, Map, SearchError, TerminationReason, Tiles, VerifyError,
+};
 
 mod utils;
 use utils::*;
@@ -8,7 +24,7 @@ fn solve(mut level: Level) {
     let map = level.map().clone();
     let solver = Solver::new(map, Strategy::Fast);
     let solution = solver.a_star_search().unwrap();
-    assert!(solver.ida_star_search().is_ok());
+    assert_solution(&level, &solver.ida_star_search().unwrap());
     let directions = solution.iter().map(|action| action.direction());
     level.do_actions(directions).unwrap();
     assert!(level.is_solved());
@@ -21,6 +37,598 @@ fn test_solver() {
     solve(load_level_from_file("assets/BoxWorld_100.xsb", 3));
 }
 
+#[test]
+fn solve_microban() {
+    for id in 1..=20 {
+        let mut level = load_level_from_file("assets/Microban_155.xsb", id);
+        let solver = Solver::new(level.map().clone(), Strategy::Fast);
+        let solution = solver.a_star_search().unwrap();
+        let directions = solution.iter().map(|action| action.direction());
+        level.do_actions(directions).unwrap();
+        assert!(level.is_solved());
+    }
+}
+
+/// Returns the lowest `(moves, pushes)` of all solutions, or the lowest
+/// `(pushes, moves)` if `pushes_first` is true, by exhaustively searching over
+/// every player step.
+fn brute_force(map: &Map, pushes_first: bool) -> Option<(usize, usize)> {
+    let mut goals: Vec<_> = map.goal_positions().iter().copied().collect();
+    goals.sort();
+    let mut boxes: Vec<_> = map.box_positions().iter().copied().collect();
+    boxes.sort();
+
+    let mut heap = BinaryHeap::new();
+    let mut costs = HashMap::new();
+    costs.insert((map.player_position(), boxes.clone()), (0, 0));
+    heap.push(Reverse(((0, 0), map.player_position(), boxes)));
+    while let Some(Reverse((cost, player_position, boxes))) = heap.pop() {
+        if costs[&(player_position, boxes.clone())] < cost {
+            continue;
+        }
+        if boxes == goals {
+            return Some(cost);
+        }
+        for direction in Direction::iter() {
+            let new_player_position = player_position + IVector2::from(direction);
+            if map[new_player_position].intersects(Tiles::Wall) {
+                continue;
+            }
+            let mut new_boxes = boxes.clone();
+            let mut is_push = false;
+            if let Some(index) = boxes.iter().position(|&b| b == new_player_position) {
+                let new_box_position = new_player_position + IVector2::from(direction);
+                if map[new_box_position].intersects(Tiles::Wall)
+                    || boxes.contains(&new_box_position)
+                {
+                    continue;
+                }
+                new_boxes[index] = new_box_position;
+                new_boxes.sort();
+                is_push = true;
+            }
+            let new_cost = match (pushes_first, is_push) {
+                (false, false) => (cost.0 + 1, cost.1),
+                (true, false) => (cost.0, cost.1 + 1),
+                (_, true) => (cost.0 + 1, cost.1 + 1),
+            };
+            let key = (new_player_position, new_boxes.clone());
+            if costs.get(&key).is_some_and(|&c| c <= new_cost) {
+                continue;
+            }
+            costs.insert(key, new_cost);
+            heap.push(Reverse((new_cost, new_player_position, new_boxes)));
+        }
+    }
+    None
+}
+
+/// Returns the lowest metrics of all solutions by exhaustively searching over
+/// every player step, where `metrics` returns the ordered metrics of actions.
+fn brute_force_metrics(map: &Map, metrics: impl Fn(&Actions) -> [usize; 6]) -> Option<[usize; 6]> {
+    let mut goals: Vec<_> = map.goal_positions().iter().copied().collect();
+    goals.sort();
+    let mut boxes: Vec<_> = map.box_positions().iter().copied().collect();
+    boxes.sort();
+
+    let mut paths = vec![(map.player_position(), boxes, None, Actions::new())];
+    let mut heap = BinaryHeap::from([Reverse((metrics(&paths[0].3), 0))]);
+    let mut costs = HashMap::new();
+    while let Some(Reverse((cost, index))) = heap.pop() {
+        let (player_position, boxes, last_pushed_box_position, actions) = paths[index].clone();
+        if boxes == goals {
+            return Some(cost);
+        }
+        for direction in Direction::iter() {
+            let new_player_position = player_position + IVector2::from(direction);
+            if map[new_player_position].intersects(Tiles::Wall) {
+                continue;
+            }
+            let mut new_boxes = boxes.clone();
+            let mut new_last_pushed_box_position = last_pushed_box_position;
+            let mut new_actions = actions.clone();
+            if let Some(index) = boxes.iter().position(|&b| b == new_player_position) {
+                let new_box_position = new_player_position + IVector2::from(direction);
+                if map[new_box_position].intersects(Tiles::Wall)
+                    || boxes.contains(&new_box_position)
+                {
+                    continue;
+                }
+                new_boxes[index] = new_box_position;
+                new_boxes.sort();
+                new_last_pushed_box_position = Some(new_box_position);
+                new_actions.push(Action::Push(direction));
+            } else {
+                new_actions.push(Action::Move(direction));
+            }
+            let new_cost = metrics(&new_actions);
+            let key = (
+                new_player_position,
+                new_boxes.clone(),
+                new_last_pushed_box_position,
+                new_actions.last().copied(),
+                new_actions[0].is_push(),
+            );
+            if costs.get(&key).is_some_and(|c| *c <= new_cost) {
+                continue;
+            }
+            costs.insert(key, new_cost);
+            paths.push((
+                new_player_position,
+                new_boxes,
+                new_last_pushed_box_position,
+                new_actions,
+            ));
+            heap.push(Reverse((new_cost, paths.len() - 1)));
+        }
+    }
+    None
+}
+
+fn move_push_metrics(actions: &Actions) -> [usize; 6] {
+    let values = actions.secondary_values();
+    [
+        actions.moves(),
+        actions.pushes(),
+        values.box_lines as usize,
+        values.box_changes as usize,
+        values.pushing_sessions as usize,
+        values.player_lines as usize,
+    ]
+}
+
+fn push_move_metrics(actions: &Actions) -> [usize; 6] {
+    let [moves, pushes, box_lines, box_changes, pushing_sessions, player_lines] =
+        move_push_metrics(actions);
+    [
+        pushes,
+        moves,
+        box_lines,
+        box_changes,
+        pushing_sessions,
+        player_lines,
+    ]
+}
+
+fn assert_solution(level: &Level, solution: &Actions) {
+    let mut level = level.clone();
+    let directions = solution.iter().map(|action| action.direction());
+    level.do_actions(directions).unwrap();
+    assert!(level.is_solved());
+    assert_eq!(level.actions(), solution);
+}
+
+#[test]
+fn optimal_move() {
+    for id in [1, 2, 3, 4, 5] {
+        let level = load_level_from_file("assets/Microban_155.xsb", id);
+        let solver = Solver::new(level.map().clone(), Strategy::OptimalMove);
+        let solution = solver.a_star_search().unwrap();
+        assert_solution(&level, &solution);
+        assert_eq!(
+            Some((solution.moves(), solution.pushes())),
+            brute_force(level.map(), false),
+            "level {id}"
+        );
+    }
+}
+
+#[test]
+fn optimal_push() {
+    for id in [1, 2, 3, 4, 5] {
+        let level = load_level_from_file("assets/Microban_155.xsb", id);
+        let solver = Solver::new(level.map().clone(), Strategy::OptimalPush);
+        let solution = solver.a_star_search().unwrap();
+        assert_solution(&level, &solution);
+        assert_eq!(
+            Some(solution.pushes()),
+            brute_force(level.map(), true).map(|(pushes, _)| pushes),
+            "level {id}"
+        );
+    }
+}
+
+#[test]
+fn ida_star_search() {
+    for id in [1, 2, 3, 4, 5] {
+        let level = load_level_from_file("assets/Microban_155.xsb", id);
+        for strategy in [Strategy::OptimalPush, Strategy::OptimalMove] {
+            let solver = Solver::new(level.map().clone(), strategy);
+            let expected = solver.a_star_search().unwrap();
+            let solution = solver.ida_star_search().unwrap();
+            assert_solution(&level, &solution);
+            assert_eq!(solution.pushes(), expected.pushes(), "level {id}");
+            if strategy == Strategy::OptimalMove {
+                assert_eq!(solution.moves(), expected.moves(), "level {id}");
+            }
+        }
+    }
+}
+
+#[test]
+fn reverse_search() {
+    for id in 1..=10 {
+        let level = load_level_from_file("assets/Microban_155.xsb", id);
+        let solver = Solver::new(level.map().clone(), Strategy::OptimalPush);
+        let solution = solver.reverse_search().unwrap();
+        assert_solution(&level, &solution);
+        assert_eq!(
+            solution.pushes(),
+            solver.a_star_search().unwrap().pushes(),
+            "level {id}"
+        );
+    }
+}
+
+#[test]
+fn bidirectional_search() {
+    for id in 1..=20 {
+        let level = load_level_from_file("assets/Microban_155.xsb", id);
+        let solver = Solver::new(level.map().clone(), Strategy::Fast);
+        assert_solution(&level, &solver.bidirectional_search().unwrap());
+    }
+
+    let map = Map::from_str(indoc! {"
+        #######
+        #.  @ #
+        ##### #
+        #.$ $ #
+        #######
+    "})
+    .unwrap();
+    let solver = Solver::new(map, Strategy::Fast);
+    assert_eq!(solver.reverse_search(), Err(SearchError::NoSolution));
+    assert_eq!(solver.bidirectional_search(), Err(SearchError::NoSolution));
+}
+
+#[test]
+fn parallel_search() {
+    for id in 1..=10 {
+        let level = load_level_from_file("assets/Microban_155.xsb", id);
+        let solver = Solver::new(level.map().clone(), Strategy::Fast);
+        assert_solution(&level, &solver.parallel_search(4).unwrap());
+
+        for strategy in [Strategy::OptimalPush, Strategy::OptimalMove] {
+            let solver = Solver::new(level.map().clone(), strategy);
+            let expected = solver.a_star_search().unwrap();
+            let solution = solver.parallel_search(4).unwrap();
+            assert_solution(&level, &solution);
+            assert_eq!(solution.pushes(), expected.pushes(), "level {id}");
+            if strategy == Strategy::OptimalMove {
+                assert_eq!(solution.moves(), expected.moves(), "level {id}");
+            }
+            assert!(solver.stats().nodes_expanded > 0);
+        }
+    }
+
+    let map = Map::from_str(indoc! {"
+        #######
+        #.  @ #
+        ##### #
+        #.$ $ #
+        #######
+    "})
+    .unwrap();
+    let solver = Solver::new(map, Strategy::Fast);
+    assert_eq!(solver.parallel_search(4), Err(SearchError::NoSolution));
+
+    let level = load_level_from_file("assets/BoxWorld_100.xsb", 3);
+    let solver =
+        Solver::new(level.map().clone(), Strategy::Fast).with_terminator(Terminator::Iterations(5));
+    assert_eq!(
+        solver.parallel_search(4),
+        Err(SearchError::Terminated(TerminationReason::Iterations))
+    );
+}
+
+#[test]
+fn corral_pruning() {
+    for id in 1..=20 {
+        let level = load_level_from_file("assets/Microban_155.xsb", id);
+        for strategy in [Strategy::OptimalPush, Strategy::OptimalMove] {
+            let solver = Solver::new(level.map().clone(), strategy);
+            assert!(solver.config().corral_pruning());
+            let solution = solver.a_star_search().unwrap();
+            assert_solution(&level, &solution);
+            let expected = Solver::with_config(
+                level.map().clone(),
+                strategy,
+                SolverConfig::new().with_corral_pruning(false),
+            )
+            .a_star_search()
+            .unwrap();
+            assert_eq!(solution.pushes(), expected.pushes(), "level {id}");
+            if strategy == Strategy::OptimalMove {
+                assert_eq!(solution.moves(), expected.moves(), "level {id}");
+            }
+        }
+    }
+
+    let level = load_level_from_file("assets/XSokoban_90.xsb", 1);
+    let solver = Solver::new(level.map().clone(), Strategy::Fast);
+    assert_solution(&level, &solver.a_star_search().unwrap());
+    let expanded = solver.stats().nodes_expanded;
+    let solver = Solver::with_config(
+        level.map().clone(),
+        Strategy::Fast,
+        SolverConfig::new().with_corral_pruning(false),
+    );
+    solver.a_star_search().unwrap();
+    assert!(expanded < solver.stats().nodes_expanded);
+}
+
+#[test]
+fn deadlock_learning() {
+    let level = load_level_from_file("assets/Microban_155.xsb", 36);
+    let solver = Solver::new(level.map().clone(), Strategy::Fast);
+    assert_solution(&level, &solver.a_star_search().unwrap());
+    let learned_deadlocks = solver.learned_deadlocks();
+    assert_eq!(
+        learned_deadlocks.len() as u64,
+        solver.stats().deadlocks_learned
+    );
+    assert!(learned_deadlocks.iter().any(|deadlock| deadlock.pruned > 0));
+    for deadlock in &learned_deadlocks {
+        assert!(!deadlock.box_positions.is_empty());
+        assert!(!deadlock.player_region.is_empty());
+    }
+    let expanded = solver.stats().nodes_expanded;
+
+    let solver = Solver::with_config(
+        level.map().clone(),
+        Strategy::Fast,
+        SolverConfig::new().with_deadlock_learning(0),
+    );
+    solver.a_star_search().unwrap();
+    assert!(solver.learned_deadlocks().is_empty());
+    assert!(expanded < solver.stats().nodes_expanded);
+
+    let solver = Solver::with_config(
+        level.map().clone(),
+        Strategy::Fast,
+        SolverConfig::new().with_deadlock_learning(1),
+    );
+    solver.a_star_search().unwrap();
+    assert_eq!(solver.learned_deadlocks().len(), 1);
+
+    for id in 1..=10 {
+        let level = load_level_from_file("assets/Microban_155.xsb", id);
+        let solver = Solver::new(level.map().clone(), Strategy::OptimalPush);
+        let solution = solver.a_star_search().unwrap();
+        let expected = Solver::with_config(
+            level.map().clone(),
+            Strategy::OptimalPush,
+            SolverConfig::new().with_deadlock_learning(0),
+        )
+        .a_star_search()
+        .unwrap();
+        assert_eq!(solution.pushes(), expected.pushes(), "level {id}");
+    }
+}
+
+#[test]
+fn optimize() {
+    for id in 1..=10 {
+        let level = load_level_from_file("assets/Microban_155.xsb", id);
+        let solution = Solver::new(level.map().clone(), Strategy::Fast)
+            .a_star_search()
+            .unwrap();
+        let optimized_solution = solver::optimize(
+            level.map(),
+            &solution,
+            Metric::Moves,
+            Duration::from_secs(10),
+        )
+        .unwrap();
+        assert_solution(&level, &optimized_solution);
+        let expected = Solver::new(level.map().clone(), Strategy::OptimalMove)
+            .a_star_search()
+            .unwrap();
+        assert_eq!(optimized_solution.moves(), expected.moves(), "level {id}");
+        assert_eq!(optimized_solution.pushes(), expected.pushes(), "level {id}");
+
+        // Ties in pushes are only broken by minimizing the moves of the pushes
+        let optimized_solution = solver::optimize(
+            level.map(),
+            &solution,
+            Metric::Pushes,
+            Duration::from_secs(10),
+        )
+        .unwrap();
+        assert_solution(&level, &optimized_solution);
+        let expected = Solver::new(level.map().clone(), Strategy::OptimalPush)
+            .a_star_search()
+            .unwrap();
+        assert_eq!(optimized_solution.pushes(), expected.pushes(), "level {id}");
+        assert!(optimized_solution.moves() <= solution.moves(), "level {id}");
+    }
+
+    // The solution is kept if there is no time to optimize it
+    let level = load_level_from_file("assets/Microban_155.xsb", 1);
+    let solution = Solver::new(level.map().clone(), Strategy::Fast)
+        .a_star_search()
+        .unwrap();
+    assert_eq!(
+        solver::optimize(level.map(), &solution, Metric::Moves, Duration::ZERO).unwrap(),
+        solution
+    );
+}
+
+#[test]
+fn minimize_moves() {
+    let map = Map::from_str(indoc! {"
+        #######
+        #@ $ .#
+        #     #
+        #######
+    "})
+    .unwrap();
+    let actions = Actions::from_str("drulrRduR").unwrap();
+    assert_eq!(
+        solver::minimize_moves(&map, &actions).unwrap(),
+        Actions::from_str("rRR").unwrap()
+    );
+    // The moves after the last push are dropped
+    let actions = Actions::from_str("rRRdlll").unwrap();
+    let minimized_actions = solver::minimize_moves(&map, &actions).unwrap();
+    assert_eq!(minimized_actions, Actions::from_str("rRR").unwrap());
+    assert!(minimized_actions.moves() < actions.moves());
+    assert_eq!(
+        solver::minimize_moves(&map, &Actions::from_str("rRlL").unwrap()),
+        Err(VerifyError::MislabeledAction { index: 3 })
+    );
+
+    for id in 1..=10 {
+        let level = load_level_from_file("assets/Microban_155.xsb", id);
+        let solution = Solver::new(level.map().clone(), Strategy::Fast)
+            .a_star_search()
+            .unwrap();
+        let minimized_solution = solver::minimize_moves(level.map(), &solution).unwrap();
+        assert_solution(&level, &minimized_solution);
+        assert!(minimized_solution.moves() <= solution.moves());
+        assert_eq!(
+            minimized_solution
+                .iter()
+                .filter(|action| action.is_push())
+                .collect::<Vec<_>>(),
+            solution
+                .iter()
+                .filter(|action| action.is_push())
+                .collect::<Vec<_>>()
+        );
+    }
+}
+
+#[test]
+fn solver_config() {
+    let level = load_level_from_file("assets/Microban_155.xsb", 1);
+    let config = SolverConfig::new()
+        .with_heuristic(Heuristic::MinimumMatching)
+        .with_corral_pruning(false);
+    let solver = Solver::with_config(level.map().clone(), Strategy::Fast, config);
+    assert_eq!(solver.config(), &config);
+    assert_eq!(
+        Solver::new(level.map().clone(), Strategy::Fast).config(),
+        &SolverConfig::default()
+    );
+
+    let configs = [
+        SolverConfig::new().with_tunnel_macros(false),
+        SolverConfig::new().with_freeze_deadlocks(false),
+        SolverConfig::new().with_closed_diagonal_deadlocks(false),
+        SolverConfig::new().with_frozen_corral_deadlocks(false),
+        SolverConfig::new().with_bipartite_deadlocks(false),
+        SolverConfig::new().with_static_deadlocks(false),
+        SolverConfig::new().with_normalization(false),
+        SolverConfig::new()
+            .with_tunnel_macros(false)
+            .with_freeze_deadlocks(false)
+            .with_closed_diagonal_deadlocks(false)
+            .with_frozen_corral_deadlocks(false)
+            .with_bipartite_deadlocks(false)
+            .with_static_deadlocks(false)
+            .with_normalization(false)
+            .with_corral_pruning(false)
+            .with_deadlock_patterns(false)
+            .with_deadlock_learning(0),
+    ];
+    for id in 1..=10 {
+        let level = load_level_from_file("assets/Microban_155.xsb", id);
+        let expected = Solver::new(level.map().clone(), Strategy::OptimalPush)
+            .a_star_search()
+            .unwrap();
+        for config in configs {
+            let solver = Solver::with_config(level.map().clone(), Strategy::OptimalPush, config);
+            let solution = solver.a_star_search().unwrap();
+            assert_solution(&level, &solution);
+            assert_eq!(solution.pushes(), expected.pushes(), "level {id}");
+        }
+    }
+}
+
+#[test]
+fn minimum_matching_heuristic() {
+    for id in [1, 2, 3, 4, 5] {
+        let level = load_level_from_file("assets/Microban_155.xsb", id);
+        let solver = Solver::with_config(
+            level.map().clone(),
+            Strategy::OptimalPush,
+            SolverConfig::new().with_heuristic(Heuristic::MinimumMatching),
+        );
+        let solution = solver.a_star_search().unwrap();
+        assert_solution(&level, &solution);
+        assert_eq!(
+            Some(solution.pushes()),
+            brute_force(level.map(), true).map(|(pushes, _)| pushes),
+            "level {id}"
+        );
+    }
+
+    // Both boxes can only reach the goal in the bottom row
+    let map = Map::from_str(indoc! {"
+        #######
+        #.  @ #
+        ##### #
+        #.$ $ #
+        #######
+    "})
+    .unwrap();
+    let solver = Solver::with_config(
+        map,
+        Strategy::Fast,
+        SolverConfig::new().with_heuristic(Heuristic::MinimumMatching),
+    );
+    assert_eq!(solver.a_star_search(), Err(SearchError::NoSolution));
+}
+
+#[test]
+fn optimal_secondary_values() {
+    // Levels where a moves/pushes optimal solution is not necessarily optimal
+    // in the secondary metrics.
+    let levels = r#"
+#######
+#    ##
+#  $  #
+#@$ . #
+#    .#
+#######
+
+#######
+# . @ #
+#   $ #
+#   # #
+#   $.#
+#######
+
+#######
+##    #
+#  $  #
+# $   #
+# .@ .#
+#######
+"#;
+    for level in Level::load_from_str(levels) {
+        let level = level.unwrap();
+        for (strategy, metrics) in [
+            (
+                Strategy::OptimalMovePush,
+                move_push_metrics as fn(&Actions) -> _,
+            ),
+            (Strategy::OptimalPushMove, push_move_metrics),
+        ] {
+            let solver = Solver::new(level.map().clone(), strategy);
+            let solution = solver.a_star_search().unwrap();
+            assert_solution(&level, &solution);
+            assert_eq!(
+                Some(metrics(&solution)),
+                brute_force_metrics(level.map(), metrics),
+                "{strategy:?}\n{}",
+                level.map()
+            );
+        }
+    }
+}
+
 #[test]
 fn weird_levels() {
     let levels = "
@@ -55,6 +663,75 @@ title: Weird 3
     }
 }
 
+#[test]
+fn lower_bounds() {
+    let map = Map::from_str(indoc! {"
+        ########
+        #.   * #
+        # #  $ #
+        # # @  #
+        ########
+    "})
+    .unwrap();
+    let solver = Solver::new(map.clone(), Strategy::Fast);
+    let lower_bounds = solver.lower_bounds();
+    assert_eq!(lower_bounds[&IVector2::new(2, 3)], 1);
+    assert_eq!(lower_bounds[&IVector2::new(3, 3)], 2);
+    assert_eq!(lower_bounds[&IVector2::new(3, 2)], 3);
+    assert!(!lower_bounds.contains_key(&IVector2::new(3, 1)));
+
+    let solution = Solver::new(map, Strategy::OptimalMove)
+        .a_star_search()
+        .unwrap();
+    assert_eq!((solution.moves(), solution.pushes()), (12, 5));
+}
+
+#[test]
+fn search_stats() {
+    let level = load_level_from_file("assets/Microban_155.xsb", 3);
+    let solver = Solver::new(level.map().clone(), Strategy::OptimalPush);
+    assert_eq!(solver.stats(), SearchStats::default());
+    solver.a_star_search().unwrap();
+    let stats = solver.stats();
+    assert!(stats.nodes_expanded > 0);
+    assert!(stats.nodes_generated >= stats.nodes_expanded);
+    assert!(stats.duplicates_pruned > 0);
+    assert!(stats.deadlocks_pruned > 0);
+    assert!(stats.peak_open_list > 0);
+    assert!(stats.elapsed > Duration::ZERO);
+    assert!(stats.best_heuristic.is_some());
+
+    // The statistics are reset for each search
+    solver.reverse_search().unwrap();
+    assert_ne!(solver.stats(), stats);
+
+    // Progress is reported periodically, and once more when the search
+    // finishes
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let solver = Solver::new(level.map().clone(), Strategy::Fast).with_progress(Duration::ZERO, {
+        let reports = reports.clone();
+        move |stats| reports.lock().unwrap().push(*stats)
+    });
+    solver.a_star_search().unwrap();
+    let reports = reports.lock().unwrap();
+    assert!(reports.len() > 1);
+    assert_eq!(reports.last(), Some(&solver.stats()));
+
+    // Searches shorter than the interval are reported when they finish
+    let reports = Arc::new(AtomicUsize::new(0));
+    let solver = Solver::new(level.map().clone(), Strategy::Fast).with_progress(
+        Duration::from_secs(3600),
+        {
+            let reports = reports.clone();
+            move |_| {
+                reports.fetch_add(1, Ordering::Relaxed);
+            }
+        },
+    );
+    solver.a_star_search().unwrap();
+    assert_eq!(reports.load(Ordering::Relaxed), 1);
+}
+
 #[test]
 fn test_terminator_iterations_limit() {
     let level = load_level_from_file("assets/BoxWorld_100.xsb", 3);
@@ -63,11 +740,96 @@ fn test_terminator_iterations_limit() {
     // With only 5 iterations, the solver should not be able to find a solution
     let solver =
         Solver::new(map.clone(), Strategy::Fast).with_terminator(Terminator::Iterations(5));
-    assert_eq!(solver.a_star_search(), Err(SearchError::Terminated));
+    assert_eq!(
+        solver.a_star_search(),
+        Err(SearchError::Terminated(TerminationReason::Iterations))
+    );
 
     // IDA* should also terminate
     let solver = Solver::new(map, Strategy::Fast).with_terminator(Terminator::Iterations(5));
-    assert_eq!(solver.ida_star_search(), Err(SearchError::Terminated));
+    assert_eq!(
+        solver.ida_star_search(),
+        Err(SearchError::Terminated(TerminationReason::Iterations))
+    );
+}
+
+#[test]
+fn test_terminator_cancellation() {
+    let level = load_level_from_file("assets/BoxWorld_100.xsb", 3);
+    let map = level.map().clone();
+
+    let cancelled = Arc::new(AtomicBool::new(true));
+    let solver = Solver::new(map.clone(), Strategy::Fast)
+        .with_terminator(Terminator::new_cancellation(cancelled.clone()));
+    assert_eq!(
+        solver.a_star_search(),
+        Err(SearchError::Terminated(TerminationReason::Cancelled))
+    );
+
+    // The flag can be cleared, and set while the search is running
+    cancelled.store(false, Ordering::Relaxed);
+    assert!(solver.a_star_search().is_ok());
+    let solver = Solver::new(
+        load_level_from_file("assets/SokHard_163.xsb", 2)
+            .map()
+            .clone(),
+        Strategy::OptimalPush,
+    )
+    .with_terminator(Terminator::new_cancellation(cancelled.clone()))
+    .with_progress(Duration::ZERO, {
+        let cancelled = cancelled.clone();
+        move |stats| {
+            if stats.nodes_expanded > 0 {
+                cancelled.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+    assert_eq!(
+        solver.a_star_search(),
+        Err(SearchError::Terminated(TerminationReason::Cancelled))
+    );
+    assert!(solver.stats().nodes_expanded > 0);
+}
+
+#[test]
+fn test_terminator_combination() {
+    let level = load_level_from_file("assets/BoxWorld_100.xsb", 3);
+    let map = level.map().clone();
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    // The condition which fired first is reported
+    let terminator = Terminator::Timeout(Duration::from_secs(60))
+        .or(Terminator::new_cancellation(cancelled.clone()))
+        .or(Terminator::Nodes(10));
+    let solver = Solver::new(map.clone(), Strategy::Fast).with_terminator(terminator.clone());
+    assert_eq!(
+        solver.a_star_search(),
+        Err(SearchError::Terminated(TerminationReason::Nodes))
+    );
+    assert!(solver.stats().nodes_generated >= 10);
+
+    cancelled.store(true, Ordering::Relaxed);
+    assert_eq!(
+        solver.ida_star_search(),
+        Err(SearchError::Terminated(TerminationReason::Cancelled))
+    );
+
+    assert_eq!(
+        Terminator::None.or(Terminator::Iterations(5)),
+        Terminator::Iterations(5)
+    );
+    assert_eq!(
+        terminator,
+        Terminator::Any(vec![
+            Terminator::Timeout(Duration::from_secs(60)),
+            Terminator::Cancelled(cancelled.clone()),
+            Terminator::Nodes(10),
+        ])
+    );
+    assert_ne!(
+        Terminator::new_cancellation(cancelled),
+        Terminator::new_cancellation(Arc::new(AtomicBool::new(true)))
+    );
 }
 
 #[expect(dead_code)]
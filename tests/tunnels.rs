@@ -0,0 +1,188 @@
+use std::str::FromStr;
+
+use indoc::indoc;
+use sokoban_core::{
+    solver::{Solver, SolverConfig, Strategy},
+    tunnels::Tunnels,
+    Direction, IVector2, Level, Map,
+};
+
+#[test]
+fn tunnels() {
+    let map = Map::from_str(indoc! {"
+        #########
+        #   #   #
+        # @$  . #
+        #   #   #
+        #########
+    "})
+    .unwrap();
+    let tunnels = Tunnels::new(&map);
+    assert_eq!(tunnels.len(), 1);
+    let tunnel = tunnels.get(IVector2::new(4, 2)).unwrap();
+    assert_eq!(tunnel.cells(), [IVector2::new(4, 2)]);
+    assert_eq!(tunnel.direction(), Direction::Right);
+    assert_eq!(
+        tunnel.push_directions(),
+        [Direction::Right, Direction::Left]
+    );
+    assert!(!tunnel.is_one_way());
+    assert!(!tunnel.is_player_tunnel());
+    assert!(tunnels.get(IVector2::new(3, 2)).is_none());
+
+    // Boxes pushed into dead ends can not be pushed out, but the player can
+    // not pass through them either
+    let map = Map::from_str(indoc! {"
+        ########
+        #@##   #
+        #    $.#
+        # ##   #
+        ########
+    "})
+    .unwrap();
+    let tunnels = Tunnels::new(&map);
+    assert_eq!(tunnels.len(), 3);
+    for position in [IVector2::new(1, 1), IVector2::new(1, 3)] {
+        let tunnel = tunnels.get(position).unwrap();
+        assert_eq!(tunnel.cells(), [position]);
+        assert_eq!(tunnel.direction(), Direction::Up);
+        assert!(tunnel.push_directions().is_empty());
+        assert!(!tunnel.is_player_tunnel());
+    }
+    assert!(tunnels.get(IVector2::new(1, 2)).is_none());
+    assert!(tunnels.get(IVector2::new(4, 2)).is_none());
+}
+
+#[test]
+fn multi_cell_tunnels() {
+    let map = Map::from_str(indoc! {"
+        ##########
+        #   ###  #
+        # @$   . #
+        #   ###  #
+        ######## #
+        #      # #
+        #      # #
+        #        #
+        ##########
+    "})
+    .unwrap();
+    let tunnels = Tunnels::new(&map);
+    assert_eq!(tunnels.len(), 3);
+
+    let tunnel = tunnels.get(IVector2::new(5, 6)).unwrap();
+    assert_eq!(tunnel.cells().len(), 3);
+    assert_eq!(
+        tunnel.cells(),
+        [
+            IVector2::new(4, 6),
+            IVector2::new(5, 6),
+            IVector2::new(6, 6)
+        ]
+    );
+    assert_eq!(tunnel.direction(), Direction::Right);
+    for position in tunnel.cells() {
+        assert_eq!(tunnels.get(*position), Some(tunnel));
+    }
+
+    let tunnel = tunnels.get(IVector2::new(8, 3)).unwrap();
+    assert_eq!(
+        tunnel.cells(),
+        [
+            IVector2::new(8, 2),
+            IVector2::new(8, 3),
+            IVector2::new(8, 4)
+        ]
+    );
+    assert_eq!(tunnel.direction(), Direction::Up);
+}
+
+#[test]
+fn one_way_tunnels() {
+    // Boxes pushed to the left can not be pushed back, since the player can
+    // not get around them
+    let map = Map::from_str(indoc! {"
+        #########
+        ##  #   #
+        #@ $  . #
+        ##  #   #
+        #########
+    "})
+    .unwrap();
+    let tunnels = Tunnels::new(&map);
+    let tunnel = tunnels.get(IVector2::new(4, 2)).unwrap();
+    assert_eq!(tunnel.push_directions(), [Direction::Right]);
+    assert!(tunnel.is_one_way());
+    assert!(!tunnel.is_player_tunnel());
+}
+
+#[test]
+fn player_tunnels() {
+    // Boxes pushed down into the tunnel can not reach the goal, and boxes can
+    // not be pushed up into it
+    let input = indoc! {"
+        #######
+        #  .  #
+        #  $  #
+        ### ###
+        #@    #
+        #######
+    "};
+    let tunnels = Tunnels::new(&Map::from_str(input).unwrap());
+    let tunnel = tunnels.get(IVector2::new(3, 2)).unwrap();
+    assert!(tunnel.push_directions().is_empty());
+    assert!(tunnel.is_player_tunnel());
+
+    // The player still passes through the tunnel to push the box
+    let mut level = Level::from_str(input).unwrap();
+    let solver = Solver::new(level.map().clone(), Strategy::Fast);
+    let solution = solver.a_star_search().unwrap();
+    level
+        .do_actions(solution.iter().map(|action| action.direction()))
+        .unwrap();
+    assert!(level.is_solved());
+
+    // Boxes can not be pushed to the right into the tunnel, and boxes pushed
+    // to the left can not be pushed back
+    let map = Map::from_str(indoc! {"
+        ########
+        #@##   #
+        #    $.#
+        # ##   #
+        ########
+    "})
+    .unwrap();
+    let tunnels = Tunnels::new(&map);
+    let tunnel = tunnels.get(IVector2::new(3, 2)).unwrap();
+    assert_eq!(tunnel.cells(), [IVector2::new(2, 2), IVector2::new(3, 2)]);
+    assert!(tunnel.is_player_tunnel());
+}
+
+#[test]
+fn tunnel_macros() {
+    let level = Level::from_str(indoc! {"
+        ##########
+        #   ###  #
+        # @$   . #
+        #   ###  #
+        ##########
+    "})
+    .unwrap();
+    let search = |config| {
+        let solver = Solver::with_config(level.map().clone(), Strategy::Fast, config);
+        let solution = solver.a_star_search().unwrap();
+        let mut level = level.clone();
+        level
+            .do_actions(solution.iter().map(|action| action.direction()))
+            .unwrap();
+        assert!(level.is_solved());
+        assert_eq!(solution.pushes(), 4);
+        solver.stats()
+    };
+
+    // The box is pushed on to the last cell of the tunnel by a single
+    // successor, skipping the nodes in between
+    let stats = search(SolverConfig::new());
+    let stats_without_macros = search(SolverConfig::new().with_tunnel_macros(false));
+    assert!(stats.nodes_expanded + 2 <= stats_without_macros.nodes_expanded);
+}